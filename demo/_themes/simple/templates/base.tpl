@@ -1,26 +1,30 @@
 <!doctype html>
-<html>
+<html lang="{{ language }}">
 <head>
   <meta charset="utf-8">
-  <link rel="icon" href="/static/favicon.png">
-  <link rel="stylesheet" href="/static/main.css">
+  <link rel="icon" href="{{ asset_url(path="favicon.png") }}">
+  <link rel="stylesheet" href="{{ asset_url(path="main.css") }}">
+  <link rel="alternate" type="application/atom+xml" title="{{ site_name }}" href="{{ feed_url }}">
+  {% for translation in translations %}
+  <link rel="alternate" hreflang="{{ translation.lang }}" href="{{ translation.url }}">
+  {% endfor %}
   {% block css %}{% endblock css %}
   <title>{{ title }}</title>
 </head>
 <body>
 <header class="clearfix">
   <section id="imglogo">
-    <a href="/index.html" title="{{ site_name }}"><img src="{{ site_logo }}"></a>
+    <a href="{{ home_url }}" title="{{ site_name }}"><img src="{{ site_logo }}"></a>
   </section>
 
   <section id="textlogo">
-    <h1 id="site-name"><a href="/index.html" title="{{ site_name }}">{{ site_name }}</a></h1>
+    <h1 id="site-name"><a href="{{ home_url }}" title="{{ site_name }}">{{ site_name }}</a></h1>
     <h2 id="site-motto">{{ site_motto }}</h2>
   </section>
 
   <nav>
     <ul>
-      <li><a href="/index.html">Blog</a></li>
+      <li><a href="{{ home_url }}">{{ t(key="blog") }}</a></li>
     </ul>
   </nav>
 </header>
@@ -31,7 +35,7 @@
 
   <aside>
     <section class="tags clearfix">
-      <h1>Tags</h1>
+      <h1>{{ t(key="tags") }}</h1>
       <ul>
       {% for tag in all_tags %}
         <li><a href="{{ tag.url }}">{{ tag.name }}<sup>{{ tag.num }}</sup></a></li>
@@ -40,9 +44,9 @@
     </section>
 
     <section class="links clearfix">
-      <h1>Links</h1>
+      <h1>{{ t(key="links") }}</h1>
       <ul>
-        <li><a href="/index.html" target="_blank">Blog</a></li>
+        <li><a href="{{ home_url }}" target="_blank">{{ t(key="blog") }}</a></li>
       </ul>
     </section>
   </aside>
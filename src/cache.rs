@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde_json;
+
+use errors::Result;
+use utils::create_file;
+
+/// build cache file name, saved in the blog build directory
+pub static CACHE_FILE: &str = ".mdblog-cache.json";
+
+/// build cache manifest
+///
+/// records the input hash of every generated page and the modification stamp of every copied
+/// media file, so that unchanged outputs are not written again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildCache {
+    /// hash of mdblog version and blog settings
+    settings: u64,
+    /// hash of theme templates and static files
+    theme: u64,
+    /// input hash of generated pages, keyed by path relative to build directory
+    pages: BTreeMap<PathBuf, u64>,
+    /// modification stamp of media files, keyed by path relative to build directory
+    media: BTreeMap<PathBuf, u64>,
+    /// modification stamp of exported theme static files, keyed by path relative to build
+    /// directory
+    statics: BTreeMap<PathBuf, u64>,
+    /// files generated by last build, relative to build directory
+    outputs: BTreeSet<PathBuf>,
+    /// files generated by current build, relative to build directory
+    #[serde(skip)]
+    current_outputs: BTreeSet<PathBuf>,
+}
+
+impl BuildCache {
+    /// load the build cache from `build_dir`
+    ///
+    /// a missing or broken cache file results in an empty cache, which means full rebuild.
+    pub fn load<P: AsRef<Path>>(build_dir: P) -> BuildCache {
+        let path = build_dir.as_ref().join(CACHE_FILE);
+        let mut content = String::new();
+        match File::open(&path).and_then(|mut f| f.read_to_string(&mut content)) {
+            Ok(_) => serde_json::from_str(&content).unwrap_or_else(|why| {
+                warn!("build cache {} is broken: {}", path.display(), why);
+                BuildCache::default()
+            }),
+            Err(_) => BuildCache::default(),
+        }
+    }
+
+    /// save the build cache into `build_dir`
+    pub fn save<P: AsRef<Path>>(&self, build_dir: P) -> Result<()> {
+        let content = serde_json::to_string(self).expect("serialize build cache error");
+        let mut f = create_file(&build_dir.as_ref().join(CACHE_FILE))?;
+        f.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    /// update settings hash, return whether it changed
+    ///
+    /// all cached pages and static files are dropped if settings changed.
+    pub fn check_settings(&mut self, hash: u64) -> bool {
+        if self.settings != hash {
+            debug!("settings changed, rebuild all pages");
+            self.settings = hash;
+            self.pages.clear();
+            self.statics.clear();
+            return true;
+        }
+        false
+    }
+
+    /// update theme hash, return whether it changed
+    ///
+    /// all cached pages and static files are dropped if theme changed.
+    pub fn check_theme(&mut self, hash: u64) -> bool {
+        if self.theme != hash {
+            debug!("theme changed, rebuild all pages");
+            self.theme = hash;
+            self.pages.clear();
+            self.statics.clear();
+            return true;
+        }
+        false
+    }
+
+    /// whether the page `dest` generated from input `hash` is up to date
+    pub fn is_page_fresh(&self, build_dir: &Path, dest: &Path, hash: u64) -> bool {
+        self.pages.get(dest) == Some(&hash) && build_dir.join(dest).exists()
+    }
+
+    /// record the input `hash` of page `dest`
+    pub fn set_page(&mut self, dest: &Path, hash: u64) {
+        self.pages.insert(dest.to_owned(), hash);
+    }
+
+    /// whether the media `dest` with modification `stamp` is up to date
+    pub fn is_media_fresh(&self, build_dir: &Path, dest: &Path, stamp: u64) -> bool {
+        self.media.get(dest) == Some(&stamp) && build_dir.join(dest).exists()
+    }
+
+    /// record the modification `stamp` of media `dest`
+    pub fn set_media(&mut self, dest: &Path, stamp: u64) {
+        self.media.insert(dest.to_owned(), stamp);
+    }
+
+    /// whether the static file `dest` is unchanged since it was exported
+    pub fn is_static_fresh(&self, build_dir: &Path, dest: &Path) -> bool {
+        match (self.statics.get(dest), file_stamp(&build_dir.join(dest))) {
+            (Some(stamp), Ok(current)) => *stamp == current,
+            _ => false,
+        }
+    }
+
+    /// record the modification `stamp` of exported static file `dest`
+    pub fn set_static(&mut self, dest: &Path, stamp: u64) {
+        self.statics.insert(dest.to_owned(), stamp);
+    }
+
+    /// record the file `dest` generated by current build
+    pub fn add_output(&mut self, dest: &Path) {
+        self.current_outputs.insert(dest.to_owned());
+    }
+
+    /// files generated by current build so far, relative to build directory
+    pub fn current_outputs(&self) -> Vec<PathBuf> {
+        self.current_outputs.iter().cloned().collect()
+    }
+
+    /// finish current build, return the files generated by last build but not by current build
+    pub fn take_orphans(&mut self) -> Vec<PathBuf> {
+        let current = ::std::mem::take(&mut self.current_outputs);
+        let orphans: Vec<PathBuf> = self.outputs.difference(&current).cloned().collect();
+        for orphan in &orphans {
+            self.pages.remove(orphan);
+            self.media.remove(orphan);
+            self.statics.remove(orphan);
+        }
+        self.outputs = current;
+        orphans
+    }
+}
+
+/// 64-bit FNV-1a hasher
+///
+/// unlike `DefaultHasher`, its algorithm is fixed, so hashes persisted in the build cache stay
+/// valid across rust releases.
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> StableHasher {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// hash of a hashable value
+pub fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = StableHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// modification stamp of file `path`, computed from its size and modification time
+pub fn file_stamp(path: &Path) -> Result<u64> {
+    let meta = path.metadata()?;
+    let modified = meta.modified()?
+                       .duration_since(UNIX_EPOCH)
+                       .unwrap_or_default();
+    Ok(hash_of(&(meta.len(), modified.as_secs(), modified.subsec_nanos())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn settings_and_theme_changes_drop_pages() {
+        let build_dir = env::temp_dir().join(format!("mdblog-cache-{}", process::id()));
+        fs::create_dir_all(&build_dir).unwrap();
+        fs::write(build_dir.join("index.html"), "").unwrap();
+        let page = Path::new("index.html");
+
+        let mut cache = BuildCache::default();
+        assert!(cache.check_settings(1));
+        assert!(cache.check_theme(2));
+        cache.set_page(page, 3);
+        assert!(!cache.check_settings(1));
+        assert!(!cache.check_theme(2));
+        assert!(cache.is_page_fresh(&build_dir, page, 3));
+        assert!(!cache.is_page_fresh(&build_dir, page, 4));
+
+        assert!(cache.check_settings(5));
+        assert!(!cache.is_page_fresh(&build_dir, page, 3));
+        cache.set_page(page, 3);
+        assert!(cache.check_theme(6));
+        assert!(!cache.is_page_fresh(&build_dir, page, 3));
+
+        cache.set_page(page, 3);
+        fs::remove_file(build_dir.join(page)).unwrap();
+        assert!(!cache.is_page_fresh(&build_dir, page, 3));
+        fs::remove_dir_all(&build_dir).unwrap();
+    }
+
+    #[test]
+    fn take_orphans_returns_outputs_of_last_build_only() {
+        let mut cache = BuildCache::default();
+        cache.add_output(Path::new("a.html"));
+        cache.add_output(Path::new("media/b.png"));
+        cache.set_page(Path::new("a.html"), 1);
+        cache.set_media(Path::new("media/b.png"), 2);
+        assert!(cache.take_orphans().is_empty());
+        assert!(cache.current_outputs().is_empty());
+
+        cache.add_output(Path::new("a.html"));
+        cache.add_output(Path::new("c.html"));
+        assert_eq!(cache.take_orphans(), vec![PathBuf::from("media/b.png")]);
+        assert!(!cache.media.contains_key(Path::new("media/b.png")));
+        assert!(cache.pages.contains_key(Path::new("a.html")));
+        let outputs: Vec<&PathBuf> = cache.outputs.iter().collect();
+        assert_eq!(outputs, vec![Path::new("a.html"), Path::new("c.html")]);
+    }
+
+    #[test]
+    fn stable_hasher_is_fnv1a() {
+        let mut hasher = StableHasher::default();
+        hasher.write(b"");
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+        let mut hasher = StableHasher::default();
+        hasher.write(b"foobar");
+        assert_eq!(hasher.finish(), 0x8594_4171_f739_67e8);
+    }
+}
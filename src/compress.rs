@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use brotli::CompressorWriter;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use errors::Result;
+use utils::create_file;
+
+/// file extensions worth precompressing
+static COMPRESSIBLE_EXTENSIONS: &[&str] = &["html", "css", "js", "xml", "json"];
+
+/// whether file `path` is worth precompressing
+pub fn is_compressible(path: &Path) -> bool {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .map(|ext| COMPRESSIBLE_EXTENSIONS.contains(&ext))
+        .unwrap_or(false)
+}
+
+/// gzip compressed `content`
+pub fn gzip(content: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(content)?;
+    Ok(encoder.finish()?)
+}
+
+/// brotli compressed `content`
+pub fn brotli(content: &[u8]) -> Result<Vec<u8>> {
+    let mut writer = CompressorWriter::new(Vec::new(), 4096, 11, 22);
+    writer.write_all(content)?;
+    writer.flush()?;
+    Ok(writer.into_inner())
+}
+
+/// compressor of a variant format
+type Compressor = fn(&[u8]) -> Result<Vec<u8>>;
+
+/// write the `.gz` and `.br` variants beside file `src`, return the paths of variants
+///
+/// variants newer than `src` are reused, variants not smaller than `src` are not written.
+pub fn compress_file(src: &Path) -> Result<Vec<PathBuf>> {
+    let modified = src.metadata()?.modified()?;
+    let mut content = Vec::new();
+    let mut variants = Vec::new();
+    let compressors: [(&str, Compressor); 2] = [("gz", gzip), ("br", brotli)];
+    for &(ext, compressor) in compressors.iter() {
+        let dest = variant_path(src, ext);
+        if let Ok(dest_modified) = dest.metadata().and_then(|m| m.modified()) {
+            if dest_modified > modified {
+                variants.push(dest);
+                continue;
+            }
+        }
+        if content.is_empty() {
+            File::open(src)?.read_to_end(&mut content)?;
+        }
+        let compressed = compressor(&content)?;
+        if compressed.len() >= content.len() {
+            continue;
+        }
+        let mut f = create_file(&dest)?;
+        f.write_all(&compressed)?;
+        variants.push(dest);
+    }
+    Ok(variants)
+}
+
+/// path of the compressed variant of `path`, e.g. `index.html` -> `index.html.gz`
+pub fn variant_path(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(ext);
+    PathBuf::from(name)
+}
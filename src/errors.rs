@@ -4,10 +4,13 @@ use std::num::ParseIntError;
 use std::net::AddrParseError;
 use config::ConfigError;
 use toml::ser::Error as TomlError;
+use toml::de::Error as TomlParseError;
+use serde_json::Error as JsonError;
 use tera::Error as TeraError;
 use hyper::error::Error as HyperError;
 use notify::Error as NotifyError;
 use glob::PatternError;
+use rayon::ThreadPoolBuildError;
 use shellexpand::LookupError;
 use std::env::VarError;
 use std::path::StripPrefixError;
@@ -36,28 +39,55 @@ pub enum Error {
     #[fail(display = "Toml error")]
     Toml(#[cause] TomlError),
 
+    #[fail(display = "Toml parse error")]
+    TomlParse(#[cause] TomlParseError),
+
+    #[fail(display = "Json error")]
+    Json(#[cause] JsonError),
+
     #[fail(display = "Path expand error")]
     PathExpend(#[cause] LookupError<VarError>),
 
     #[fail(display = "Path strip prefix error")]
     PathStripPrefix(#[cause] StripPrefixError),
 
+    #[fail(display = "Thread pool build error")]
+    ThreadPool(#[cause] ThreadPoolBuildError),
+
     #[fail(display = "Template error: {}", _0)]
     Template(String),
     // Template(#[cause] ::tera::Error),
 
+    #[fail(display = "template {} error: {}", _0, _1)]
+    TemplateFile(String, String),
+
+    #[fail(display = "scss {:?} line {}: {}", _0, _1, _2)]
+    Scss(PathBuf, usize, String),
+
     #[fail(display = "Server error")]
     Hyper(#[cause] HyperError),
 
     #[fail(display = "Argument error: {}", _0)]
     Argument(String),
 
+    #[fail(display = "command `{}` failed: {}", _0, _1)]
+    Command(String, String),
+
     #[fail(display = "blog root directory {:?} already exists", _0)]
     RootDirExisted(PathBuf),
 
     #[fail(display = "blog theme {} not found", _0)]
     ThemeNotFound(String),
 
+    #[fail(display = "blog theme {} already exists", _0)]
+    ThemeExisted(String),
+
+    #[fail(display = "blog theme {} is invalid: {}", _0, _1)]
+    ThemeInvalid(String, String),
+
+    #[fail(display = "blog theme {} extends itself", _0)]
+    ThemeExtendsCycle(String),
+
     #[fail(display = "blog theme {} in use, can not be deleted", _0)]
     ThemeInUse(String),
 
@@ -67,8 +97,14 @@ pub enum Error {
     #[fail(display = "post path {:?} already existed", _0)]
     PostPathExisted(PathBuf),
 
-    #[fail(display = "post {:?} head part format error", _0)]
-    PostHead(PathBuf),
+    #[fail(display = "post {:?} template {} not found in blog theme {}", _0, _1, _2)]
+    PostTemplateNotFound(PathBuf, String, String),
+
+    #[fail(display = "post {:?} rendering with template {} error: {}", _0, _1, _2)]
+    PostRender(PathBuf, String, String),
+
+    #[fail(display = "post {:?} head part format error at line {}", _0, _1)]
+    PostHead(PathBuf, usize),
 
     #[fail(display = "post {:?} has not body part", _0)]
     PostNoBody(PathBuf),
@@ -116,6 +152,18 @@ impl From<TomlError> for Error {
      }
 }
 
+impl From<TomlParseError> for Error {
+     fn from(err: TomlParseError) -> Error {
+         Error::TomlParse(err)
+     }
+}
+
+impl From<JsonError> for Error {
+     fn from(err: JsonError) -> Error {
+         Error::Json(err)
+     }
+}
+
 impl From<LookupError<VarError>> for Error {
      fn from(err: LookupError<VarError>) -> Error {
          Error::PathExpend(err)
@@ -128,9 +176,16 @@ impl From<StripPrefixError> for Error {
      }
 }
 
+impl From<ThreadPoolBuildError> for Error {
+     fn from(err: ThreadPoolBuildError) -> Error {
+         Error::ThreadPool(err)
+     }
+}
+
 impl From<TeraError> for Error {
      fn from(err: TeraError) -> Error {
-         Error::Template(err.description().to_string())
+         let causes: Vec<String> = err.iter().map(|x| x.to_string()).collect();
+         Error::Template(causes.join(": "))
      }
 }
 
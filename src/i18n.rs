@@ -0,0 +1,142 @@
+use chrono::{DateTime, Datelike, Local};
+use serde_json::Value as TeraValue;
+use tera::{GlobalFn, Result as TeraResult};
+use toml::Value;
+use toml::value::Table;
+
+/// the language whose translations are used for keys missing in other languages
+pub static DEFAULT_LANGUAGE: &str = "en";
+
+/// translations of a language, loaded from the theme `i18n/<lang>.toml` files
+///
+/// besides the translated strings of templates, the table may contain date formatting keys:
+///
+/// * `date_format`, `datetime_format`: format of dates and datetimes, e.g. `%Y-%m-%d`
+/// * `months`, `months_short`: month names used by `%B` and `%b`, from January
+/// * `weekdays`, `weekdays_short`: weekday names used by `%A` and `%a`, from Monday
+#[derive(Debug, Clone, Default)]
+pub struct I18n {
+    /// language code, e.g. `en`
+    pub language: String,
+    /// translations, with the default language translations as fallback
+    table: Table,
+}
+
+impl I18n {
+    /// translations of `language`, `tables` are ordered from the fallback to the preferred
+    pub fn new(language: &str, tables: Vec<Table>) -> I18n {
+        let mut table = Table::new();
+        for t in tables {
+            table.extend(t);
+        }
+        I18n {
+            language: language.to_string(),
+            table: table,
+        }
+    }
+
+    /// the translated string of `key`, `key` itself if not translated
+    pub fn translate(&self, key: &str) -> String {
+        match self.table.get(key).and_then(|x| x.as_str()) {
+            Some(value) => value.to_string(),
+            None => key.to_string(),
+        }
+    }
+
+    /// names of array `key`, e.g. `months`
+    fn names(&self, key: &str) -> Option<Vec<&str>> {
+        self.table
+            .get(key)
+            .and_then(|x| x.as_array())
+            .map(|x| x.iter().filter_map(Value::as_str).collect())
+    }
+
+    /// format `datetime` with the translated format of `key`, `default` if not translated
+    ///
+    /// `%B`, `%b`, `%A` and `%a` are replaced with the translated month and weekday names.
+    pub fn format_datetime(&self, datetime: &DateTime<Local>, key: &str, default: &str) -> String {
+        let fmt = self.table.get(key).and_then(|x| x.as_str()).unwrap_or(default);
+        let month = datetime.month0() as usize;
+        let weekday = datetime.weekday().num_days_from_monday() as usize;
+        let mut localized = String::with_capacity(fmt.len());
+        let mut chars = fmt.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                localized.push(c);
+                continue;
+            }
+            let spec = match chars.next() {
+                Some(spec) => spec,
+                None => {
+                    localized.push(c);
+                    break;
+                },
+            };
+            let name = match spec {
+                'B' => self.names("months").and_then(|x| x.get(month).cloned()),
+                'b' => self.names("months_short").and_then(|x| x.get(month).cloned()),
+                'A' => self.names("weekdays").and_then(|x| x.get(weekday).cloned()),
+                'a' => self.names("weekdays_short").and_then(|x| x.get(weekday).cloned()),
+                _ => None,
+            };
+            match name {
+                Some(name) => localized.push_str(&name.replace('%', "%%")),
+                None => {
+                    localized.push(c);
+                    localized.push(spec);
+                },
+            }
+        }
+        datetime.format(&localized).to_string()
+    }
+}
+
+/// template global function `t(key="tags")`, return the translated string of `key`
+pub fn make_t_fn(i18n: I18n) -> GlobalFn {
+    Box::new(move |args| -> TeraResult<TeraValue> {
+        match args.get("key").and_then(|x| x.as_str()) {
+            Some(key) => Ok(TeraValue::String(i18n.translate(key))),
+            None => Err("global function `t` requires a `key` argument".into()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use toml;
+
+    use super::*;
+
+    /// translations of `language` from the toml `tables`, ordered from the fallback
+    fn i18n(language: &str, tables: &[&str]) -> I18n {
+        I18n::new(language, tables.iter().map(|x| toml::from_str(x).unwrap()).collect())
+    }
+
+    #[test]
+    fn format_datetime_translates_names() {
+        let en = r#"date_format = "%b %d, %Y"
+months_short = ["Jan", "Feb", "Mar"]"#;
+        let zh = r#"date_format = "%Y年%m月%d日 %A"
+weekdays = ["星期一", "星期二", "星期三", "星期四", "星期五", "星期六", "星期日"]"#;
+        // 2018-03-04 is a Sunday
+        let datetime = Local.ymd(2018, 3, 4).and_hms(5, 6, 7);
+        let en = i18n("en", &[en]);
+        assert_eq!(en.format_datetime(&datetime, "date_format", "%Y"), "Mar 04, 2018");
+        assert_eq!(en.format_datetime(&datetime, "datetime_format", "%Y-%m-%d %H:%M"),
+                   "2018-03-04 05:06");
+        let zh = i18n("zh", &[r#"date_format = "%Y-%m-%d""#, zh]);
+        assert_eq!(zh.format_datetime(&datetime, "date_format", "%Y"), "2018年03月04日 星期日");
+    }
+
+    #[test]
+    fn format_datetime_keeps_untranslated_and_escaped_specifiers() {
+        let datetime = Local.ymd(2018, 3, 4).and_hms(5, 6, 7);
+        let names = r#"months = ["100%"]
+weekdays_short = ["Mon"]"#;
+        let i18n = i18n("en", &[names]);
+        assert_eq!(i18n.format_datetime(&datetime, "date_format", "%B %a %%"), "March Sun %");
+        let datetime = Local.ymd(2018, 1, 1).and_hms(0, 0, 0);
+        assert_eq!(i18n.format_datetime(&datetime, "date_format", "%B %a"), "100% Mon");
+    }
+}
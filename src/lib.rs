@@ -26,37 +26,67 @@ extern crate glob;
 extern crate mime_guess;
 extern crate shellexpand;
 extern crate percent_encoding;
+extern crate rayon;
+extern crate minify_html;
+extern crate minify_js;
+extern crate lightningcss;
+extern crate flate2;
+extern crate brotli;
+extern crate grass;
+extern crate semver;
+extern crate tar;
+extern crate diffy;
+extern crate zip;
+#[cfg(target_os = "linux")]
+extern crate libc;
 
+mod cache;
+mod compress;
 mod errors;
+mod i18n;
+mod minify;
 mod settings;
 mod post;
 mod theme;
 mod utils;
 mod service;
+mod template;
 
 use std::thread;
 use std::collections::BTreeMap;
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
 use std::time::{Duration, Instant};
+use std::sync::Arc;
 use std::sync::mpsc::channel;
 
 use glob::Pattern;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rayon::prelude::*;
 use hyper::server::Http;
-use tera::{Context, Tera};
+use tera::{Context, Template, Tera};
+use tera::ast::Node;
 use walkdir::{DirEntry, WalkDir};
 use serde_json::{Map, Value};
-use chrono::Local;
+use chrono::{Local, TimeZone};
 use notify::{DebouncedEvent, RecursiveMode, Watcher, watcher};
 
 use config::Config;
+use cache::{BuildCache, file_stamp, hash_of};
+use compress::{compress_file, is_compressible};
+use minify::minify_html;
 pub use errors::{Error, Result};
 pub use settings::Settings;
 pub use theme::Theme;
+use theme::{diff_theme, install_theme, make_asset_url_fn, update_theme, upgrade_theme};
 pub use post::Post;
-use service::HttpService;
-pub use utils::{create_file, log_error};
+use post::tag_url;
+use i18n::{I18n, make_t_fn};
+use template::{calls_function, date_format, make_get_post_fn, make_get_tag_fn, make_now_fn,
+               make_url_for_fn, markdown, reading_time};
+use service::{HttpService, LiveReload};
+pub use utils::{copy_file, create_file, log_error};
+use utils::{link_dir, sibling_dir, swap_dir};
 
 
 /// blog object
@@ -67,12 +97,16 @@ pub struct Mdblog {
     settings: Settings,
     /// blog theme
     theme: Theme,
-    /// blog render
-    renderer: Tera,
+    /// blog renders of every language
+    renderers: BTreeMap<String, Tera>,
+    /// translations of every language
+    i18n: BTreeMap<String, I18n>,
     /// collection of blog posts
-    posts: Vec<Rc<Post>>,
-    /// tagged posts
-    tags: BTreeMap<String, Vec<Rc<Post>>>,
+    posts: Vec<Arc<Post>>,
+    /// tagged posts of every language
+    tags: BTreeMap<String, BTreeMap<String, Vec<Arc<Post>>>>,
+    /// thread pool for rendering pages
+    pool: ThreadPool,
 }
 
 impl Mdblog {
@@ -81,17 +115,31 @@ impl Mdblog {
         let root = root.as_ref();
         let settings: Settings = Default::default();
         let theme = Mdblog::get_theme(root, &settings.theme)?;
-        let renderer = Mdblog::get_renderer(root, &settings.theme)?;
+        let mut renderers = BTreeMap::new();
+        renderers.insert(settings.language.clone(),
+                         Mdblog::get_renderer(&theme, &settings, &settings.language)?);
+        let mut i18n = BTreeMap::new();
+        i18n.insert(settings.language.clone(), I18n::default());
         Ok(Mdblog {
             root: root.to_owned(),
             settings: settings,
             theme: theme,
-            renderer: renderer,
+            renderers: renderers,
+            i18n: i18n,
             posts: Vec::new(),
             tags: BTreeMap::new(),
+            pool: ThreadPoolBuilder::new().build()?,
         })
     }
 
+    /// set the number of threads used for rendering pages
+    ///
+    /// if `jobs` is `0`, use the number of logical CPUs.
+    pub fn set_jobs(&mut self, jobs: usize) -> Result<()> {
+        self.pool = ThreadPoolBuilder::new().num_threads(jobs).build()?;
+        Ok(())
+    }
+
     /// load customize settings
     ///
     /// layered configuration system:
@@ -104,10 +152,66 @@ impl Mdblog {
         settings.merge(config::File::with_name("Config.toml"))?;
         settings.merge(config::Environment::with_prefix("BLOG"))?;
         self.settings = settings.try_into()?;
-        self.renderer = Mdblog::get_renderer(&self.root, &self.settings.theme)?;
+        self.load_theme()?;
+        Ok(())
+    }
+
+    /// load the theme and renderers of blog settings
+    pub fn load_theme(&mut self) -> Result<()> {
+        if self.is_multilingual() && !self.settings.languages.contains(&self.settings.language) {
+            return Err(Error::Argument(format!("blog language {} is not in languages",
+                                               self.settings.language)));
+        }
+        self.theme = Mdblog::get_theme(&self.root, &self.settings.theme)?;
+        self.theme.resolve_options(&self.settings.theme_options)?;
+        let mut renderers = BTreeMap::new();
+        let mut i18n = BTreeMap::new();
+        for lang in self.languages() {
+            renderers.insert(lang.clone(),
+                             Mdblog::get_renderer(&self.theme, &self.settings, &lang)?);
+            i18n.insert(lang.clone(), self.theme.i18n(&lang)?);
+        }
+        self.renderers = renderers;
+        self.i18n = i18n;
         Ok(())
     }
 
+    /// whether the blog is multilingual, see `languages` setting
+    fn is_multilingual(&self) -> bool {
+        !self.settings.languages.is_empty()
+    }
+
+    /// the blog languages, only the blog language for single language blog
+    fn languages(&self) -> Vec<String> {
+        if self.is_multilingual() {
+            self.settings.languages.clone()
+        } else {
+            vec![self.settings.language.clone()]
+        }
+    }
+
+    /// the language of `post`
+    fn post_lang<'a>(&'a self, post: &'a Post) -> &'a str {
+        post.lang().unwrap_or(&self.settings.language)
+    }
+
+    /// the language directory of `lang` pages, `None` for single language blog
+    fn lang_dir<'a>(&self, lang: &'a str) -> Option<&'a str> {
+        if self.is_multilingual() {
+            Some(lang)
+        } else {
+            None
+        }
+    }
+
+    /// the url of page `name` of language `lang`, e.g. `/zh/index.html`
+    fn lang_url(&self, lang: &str, name: &str) -> String {
+        match self.lang_dir(lang) {
+            Some(dir) => format!("/{}/{}", dir, name),
+            None => format!("/{}", name),
+        }
+    }
+
     /// get theme
     pub fn get_theme<P: AsRef<Path>>(root: P, name: &str) -> Result<Theme> {
         let mut theme = Theme::new(root.as_ref());
@@ -115,19 +219,29 @@ impl Mdblog {
         Ok(theme)
     }
 
-    pub fn get_renderer<P: AsRef<Path>>(root: P, theme_name: &str) -> Result<Tera> {
-        let template_dir = root.as_ref()
-                               .join("_themes")
-                               .join(theme_name)
-                               .join("templates");
-        debug!("template dir: {}", template_dir.display());
-        let renderer = Tera::new(&format!("{}/*", template_dir.display()))?;
+    /// get renderer of language `lang` with the templates of `theme`
+    pub fn get_renderer(theme: &Theme, settings: &Settings, lang: &str) -> Result<Tera> {
+        debug!("loading theme({}) templates ...", theme.name);
+        let mut renderer = Tera::default();
+        let mut templates = theme.templates()?;
+        templates.extend(theme.site_templates()?);
+        for &(name, content) in &templates {
+            Template::new(name, None, content).map_err(|why| template_error(name, why))?;
+        }
+        renderer.add_raw_templates(templates)?;
+        let assets = theme.assets(settings.minify)?;
+        renderer.register_global_function("asset_url", make_asset_url_fn(assets));
+        renderer.register_global_function("url_for", make_url_for_fn(BTreeMap::new()));
+        renderer.register_global_function("now", make_now_fn());
+        renderer.register_global_function("t", make_t_fn(theme.i18n(lang)?));
+        renderer.register_filter("date_format", date_format);
+        renderer.register_filter("reading_time", reading_time);
+        renderer.register_filter("markdown", markdown);
         Ok(renderer)
     }
 
     pub fn load(&mut self) -> Result<()> {
-        let mut posts: Vec<Rc<Post>> = Vec::new();
-        let mut tags: BTreeMap<String, Vec<Rc<Post>>> = BTreeMap::new();
+        let mut posts = Vec::new();
         let posts_dir = self.root.join("posts");
         let walker = WalkDir::new(&posts_dir).into_iter();
 
@@ -142,22 +256,56 @@ impl Mdblog {
                                            .expect("create post path error")
                                            .to_owned());
             post.load()?;
-            let post = Rc::new(post);
-            posts.push(post.clone());
+            posts.push(post);
+        }
+        self.set_posts(posts);
+        Ok(())
+    }
+
+    /// set blog posts, group them by languages and tags
+    fn set_posts(&mut self, mut posts: Vec<Post>) {
+        if self.is_multilingual() {
+            for post in &mut posts {
+                post.set_language(&self.settings.languages, &self.settings.language);
+            }
+        }
+        let mut posts: Vec<Arc<Post>> = posts.into_iter().map(Arc::new).collect();
+        let mut tags: BTreeMap<String, BTreeMap<String, Vec<Arc<Post>>>> =
+            self.languages().into_iter().map(|lang| (lang, BTreeMap::new())).collect();
+        for post in &posts {
             if !post.is_hidden() {
+                let lang_tags = tags.get_mut(self.post_lang(post))
+                                    .expect("get language tags error");
                 for tag in post.tags() {
-                    let mut ps = tags.entry(tag.to_string()).or_insert(Vec::new());
+                    let mut ps = lang_tags.entry(tag.to_string()).or_insert(Vec::new());
                     ps.push(post.clone());
                 }
             }
         }
         posts.sort_by(|p1, p2| p2.datetime().cmp(&p1.datetime()));
-        for (_, tag_posts) in tags.iter_mut() {
+        for tag_posts in tags.values_mut().flat_map(|x| x.values_mut()) {
             tag_posts.sort_by(|p1, p2| p2.datetime().cmp(&p1.datetime()));
         }
         self.posts = posts;
         self.tags = tags;
-        Ok(())
+
+        let posts = self.posts
+                        .iter()
+                        .map(|post| {
+                            let path = post.path.strip_prefix("posts").unwrap_or(&post.path);
+                            (path.to_string_lossy().replace('\\', "/"), post.clone())
+                        })
+                        .collect::<BTreeMap<String, Arc<Post>>>();
+        let multilingual = self.is_multilingual();
+        for (lang, renderer) in self.renderers.iter_mut() {
+            let i18n = self.i18n[lang].clone();
+            let lang_dir = if multilingual { Some(lang.clone()) } else { None };
+            renderer.register_global_function("url_for", make_url_for_fn(posts.clone()));
+            let tags = self.tags[lang].clone();
+            renderer.register_global_function("get_post",
+                                              make_get_post_fn(posts.clone(), i18n.clone()));
+            renderer.register_global_function("get_tag", make_get_tag_fn(tags, lang_dir, i18n));
+        }
     }
 
     /// init Mdblog with `theme`.
@@ -190,29 +338,41 @@ impl Mdblog {
         Ok(())
     }
 
-    /// serve the blog static files built in `root/_build/` directory.
+    /// build the blog and serve the static files built in `root/_build/` directory.
+    ///
+    /// the served pages are reloaded after every successful rebuild, a failed build is shown as
+    /// the error overlay instead of stopping the server.
     pub fn serve(&mut self, port: u16) -> Result<()> {
         let addr_str = format!("127.0.0.1:{}", port);
         let server_url = format!("http://{}", &addr_str);
         let addr = addr_str.parse()?;
         let build_dir = self.get_build_dir()?;
+        let reload = LiveReload::default();
+        if let Err(ref e) = self.load().and_then(|_| self.build()) {
+            log_error(e);
+            reload.fail(e);
+        }
+        let service_reload = reload.clone();
         info!("server blog at {}", server_url);
 
         let child = thread::spawn(move || {
             let server = Http::new()
-                .bind(&addr, move || Ok(HttpService{root: build_dir.clone()}))
+                .bind(&addr, move || {
+                    Ok(HttpService{root: build_dir.clone(), reload: service_reload.clone()})
+                })
                 .expect("server start error");
             server.run().unwrap();
         });
 
         open::that(server_url)?;
-        self.watch()?;
+        self.watch(&reload)?;
         child.join().expect("Couldn't join the server thread");
 
         Ok(())
     }
 
-    fn watch(&mut self) -> Result<()> {
+    /// rebuild the blog on changes of blog root directory, and notify `reload` of the rebuilds
+    fn watch(&mut self, reload: &LiveReload) -> Result<()> {
         let (tx, rx) = channel();
         let ignore_patterns = self.get_ignore_patterns()?;
         info!("watching dir: {}", self.root.display());
@@ -241,15 +401,17 @@ impl Mdblog {
                             last_run = Some(now);
                             info!("Modified file: {}", fpath.display());
                             info!("Rebuild blog again...");
-                            if let Err(ref e) = self.load() {
-                                log_error(e);
-                                continue
-                            }
-                            if let Err(ref e) = self.build() {
+                            if let Err(ref e) = self.rebuild() {
                                 log_error(e);
+                                reload.fail(e);
                                 continue
                             }
                             info!("Rebuild done!");
+                            if is_stylesheet(fpath) {
+                                reload.notify("css");
+                            } else {
+                                reload.notify("reload");
+                            }
                         },
                         _ => {},
                     }
@@ -260,6 +422,13 @@ impl Mdblog {
         Ok(())
     }
 
+    /// reload the theme and posts, and build the blog
+    fn rebuild(&mut self) -> Result<()> {
+        self.load_theme()?;
+        self.load()?;
+        self.build()
+    }
+
     fn get_build_dir(&self) -> Result<PathBuf> {
         let expanded_path = shellexpand::full(&self.settings.build_dir)?.into_owned();
         let build_dir = PathBuf::from(expanded_path.to_string());
@@ -276,7 +445,10 @@ impl Mdblog {
                             .to_str()
                             .expect("get build dir error")
                             .to_string();
-        patterns.push(Pattern::new(&format!("{}/**/*", build_dir.trim_right_matches("/")))?);
+        let build_dir = Pattern::escape(build_dir.trim_right_matches("/"));
+        // the build directory itself is swapped by every build
+        patterns.push(Pattern::new(&build_dir)?);
+        patterns.push(Pattern::new(&format!("{}/**/*", build_dir))?);
         Ok(patterns)
     }
 
@@ -309,15 +481,122 @@ impl Mdblog {
         Ok(())
     }
 
+    /// export the blog static files into the build directory.
+    ///
+    /// the blog is exported into a staging directory beside the build directory,
+    /// which replaces the build directory only when the whole export succeeded,
+    /// so the build directory is always a consistent snapshot.
+    /// a symlinked build directory is staged beside its target, and the blog is exported in
+    /// place if the build directory can not be renamed, e.g. a mount point.
     pub fn export(&self) -> Result<()> {
-        self.export_media()?;
-        self.export_static()?;
-        self.export_posts()?;
-        self.export_index()?;
-        self.export_tags()?;
+        // stage beside the real build directory, so a symlinked build directory is kept
+        let build_dir = self.get_build_dir()?;
+        let build_dir = build_dir.canonicalize().unwrap_or(build_dir);
+        let staging_dir = sibling_dir(&build_dir, "staging")?;
+        if staging_dir.exists() {
+            std::fs::remove_dir_all(&staging_dir)?;
+        }
+        if build_dir.exists() {
+            link_dir(&build_dir, &staging_dir)?;
+        }
+        if let Err(e) = self.export_to(&staging_dir) {
+            std::fs::remove_dir_all(&staging_dir)?;
+            return Err(e);
+        }
+        if let Err(ref e) = swap_dir(&staging_dir, &build_dir) {
+            warn!("can not swap {} into place({}), build in place", build_dir.display(), e);
+            std::fs::remove_dir_all(&staging_dir)?;
+            return self.export_to(&build_dir);
+        }
+        if staging_dir.exists() {
+            std::fs::remove_dir_all(&staging_dir)?;
+        }
         Ok(())
     }
 
+    /// export the blog static files into `build_dir`.
+    ///
+    /// outputs whose inputs are unchanged since last build are skipped,
+    /// see the build cache file `_build/.mdblog-cache.json`.
+    pub fn export_to(&self, build_dir: &Path) -> Result<()> {
+        let mut cache = BuildCache::load(build_dir);
+        cache.check_settings(self.settings_hash()?);
+        cache.check_theme(self.theme.hash());
+        self.export_media(build_dir, &mut cache)?;
+        self.export_static(build_dir, &mut cache)?;
+        self.export_posts(build_dir, &mut cache)?;
+        self.export_index(build_dir, &mut cache)?;
+        self.export_not_found(build_dir, &mut cache)?;
+        self.export_tags(build_dir, &mut cache)?;
+        self.export_feeds(build_dir, &mut cache)?;
+        if self.settings.precompress {
+            self.export_compressed(build_dir, &mut cache)?;
+        }
+        self.remove_orphans(build_dir, &cache.take_orphans())?;
+        cache.save(build_dir)?;
+        Ok(())
+    }
+
+    /// remove all files in the build directory, except the preserved paths.
+    pub fn clean(&self) -> Result<()> {
+        let build_dir = self.get_build_dir()?;
+        if !build_dir.exists() {
+            return Ok(());
+        }
+        let preserved_patterns = self.get_preserved_patterns()?;
+        // `filter_entry` can not skip directories when walking contents first
+        let walker = WalkDir::new(&build_dir).min_depth(1).contents_first(true);
+        for entry in walker {
+            let entry = entry.expect("get walker entry error");
+            let path = entry.path();
+            if is_preserved(path.strip_prefix(&build_dir)?, &preserved_patterns) {
+                continue;
+            }
+            if entry.file_type().is_dir() {
+                if std::fs::read_dir(path)?.next().is_none() {
+                    std::fs::remove_dir(path)?;
+                }
+            } else {
+                debug!("removing {}", path.display());
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// remove files generated by last build but not by current build.
+    fn remove_orphans(&self, build_dir: &Path, orphans: &[PathBuf]) -> Result<()> {
+        let preserved_patterns = self.get_preserved_patterns()?;
+        for orphan in orphans {
+            if is_preserved(orphan, &preserved_patterns) {
+                continue;
+            }
+            let path = build_dir.join(orphan);
+            if !path.is_file() {
+                continue;
+            }
+            debug!("removing orphan {}", path.display());
+            std::fs::remove_file(&path)?;
+            let mut parent = path.parent();
+            while let Some(dir) = parent {
+                if dir == build_dir || std::fs::read_dir(dir)?.next().is_some() {
+                    break;
+                }
+                std::fs::remove_dir(dir)?;
+                parent = dir.parent();
+            }
+        }
+        Ok(())
+    }
+
+    fn get_preserved_patterns(&self) -> Result<Vec<Pattern>> {
+        let mut patterns = Vec::new();
+        for path in &self.settings.preserved_paths {
+            patterns.push(Pattern::new(path)?);
+        }
+        Ok(patterns)
+    }
+
     pub fn export_config(&self) -> Result<()> {
         let content = toml::to_string(&self.settings)?;
         let mut config_file = create_file(&self.root.join("Config.toml"))?;
@@ -325,89 +604,333 @@ impl Mdblog {
         Ok(())
     }
 
+    /// the destination of `media` file, relative to the build directory
     pub fn media_dest<P: AsRef<Path>>(&self, media: P) -> Result<PathBuf> {
-        let build_dir = self.get_build_dir()?;
         let rel_path = media.as_ref()
                             .strip_prefix(&self.root.join("media"))?
                             .to_owned();
-        Ok(build_dir.join(rel_path))
+        Ok(rel_path)
     }
 
-    pub fn export_media(&self) -> Result<()> {
+    pub fn export_media(&self, build_dir: &Path, cache: &mut BuildCache) -> Result<()> {
         debug!("exporting media ...");
         let walker = WalkDir::new(&self.root.join("media")).into_iter();
         for entry in walker.filter_entry(|e| !is_hidden(e)) {
             let entry = entry.expect("get walker entry error");
             let src_path = entry.path();
+            let rel_dest = self.media_dest(src_path)?;
             if src_path.is_dir() {
-                std::fs::create_dir_all(self.media_dest(src_path)?)?;
+                std::fs::create_dir_all(build_dir.join(rel_dest))?;
                 continue;
             }
-            std::fs::copy(src_path, self.media_dest(src_path)?)?;
+            cache.add_output(&rel_dest);
+            let stamp = file_stamp(src_path)?;
+            if cache.is_media_fresh(build_dir, &rel_dest, stamp) {
+                continue;
+            }
+            copy_file(src_path, &build_dir.join(&rel_dest))?;
+            cache.set_media(&rel_dest, stamp);
         }
         Ok(())
     }
 
-    pub fn export_static(&self) -> Result<()> {
-        let build_dir = self.get_build_dir()?;
-        self.theme.export_static(&build_dir)?;
+    pub fn export_static(&self, build_dir: &Path, cache: &mut BuildCache) -> Result<()> {
+        debug!("exporting theme({}) static ...", self.theme.name);
+        for (rel_dest, content) in self.theme.static_outputs(self.settings.minify)? {
+            cache.add_output(&rel_dest);
+            if cache.is_static_fresh(build_dir, &rel_dest) {
+                continue;
+            }
+            let dest = build_dir.join(&rel_dest);
+            create_file(&dest)?.write_all(&content)?;
+            cache.set_static(&rel_dest, file_stamp(&dest)?);
+        }
         Ok(())
     }
 
-    pub fn export_posts(&self) -> Result<()> {
-        let build_dir = self.get_build_dir()?;
+    pub fn export_posts(&self, build_dir: &Path, cache: &mut BuildCache) -> Result<()> {
+        let tags_hashes: BTreeMap<String, u64> = self.languages()
+                                                     .into_iter()
+                                                     .map(|lang| {
+                                                         let hash = self.tags_hash(&lang);
+                                                         (lang, hash)
+                                                     })
+                                                     .collect();
+        let mut stale_posts = Vec::new();
         for post in &self.posts {
-            let dest = build_dir.join(post.dest());
-            let mut f = create_file(&dest)?;
-            let html = self.render_post(post)?;
-            f.write(html.as_bytes())?;
+            cache.add_output(&post.dest());
+            let translations = serde_json::to_string(&self.post_translations(post))?;
+            let hash = hash_of(&(tags_hashes[self.post_lang(post)], post.hash(), translations));
+            if !cache.is_page_fresh(build_dir, &post.dest(), hash) {
+                stale_posts.push((post, hash));
+            }
+        }
+        self.pool.install(|| {
+            stale_posts.par_iter()
+                       .map(|&(post, _)| {
+                           let html = self.render_post(post)?;
+                           self.write_html(&build_dir.join(post.dest()), &html)
+                       })
+                       .collect::<Result<()>>()
+        })?;
+        for (post, hash) in stale_posts {
+            cache.set_page(&post.dest(), hash);
         }
         Ok(())
     }
 
-    pub fn export_index(&self) -> Result<()> {
-        let build_dir = self.get_build_dir()?;
-        let dest = build_dir.join("index.html");
-        let mut f = create_file(&dest)?;
-        let html = self.render_index()?;
-        f.write(html.as_bytes())?;
+    /// export the index page of every language
+    pub fn export_index(&self, build_dir: &Path, cache: &mut BuildCache) -> Result<()> {
+        for lang in self.languages() {
+            let posts = self.get_posts_maps(&lang, &self.lang_posts(&lang))?;
+            let posts = serde_json::to_string(&posts)?;
+            let hash = hash_of(&(self.tags_hash(&lang), posts));
+            self.export_lang_page(build_dir, cache, &lang, "index.html", hash, || {
+                self.render_index(&lang)
+            })?;
+        }
         Ok(())
     }
 
-    pub fn export_tags(&self) -> Result<()> {
-        let build_dir = self.get_build_dir()?;
-        for tag in self.tags.keys() {
-            let dest = build_dir.join(format!("blog/tags/{}.html", tag));
-            let mut f = create_file(&dest)?;
-            let html = self.render_tag(tag)?;
-            f.write(html.as_bytes())?;
+    /// export the not found page `404.html` of every language, if the theme has `404.tpl`
+    pub fn export_not_found(&self, build_dir: &Path, cache: &mut BuildCache) -> Result<()> {
+        if !self.renderers[&self.settings.language].templates.contains_key("404.tpl") {
+            return Ok(());
+        }
+        for lang in self.languages() {
+            let hash = self.tags_hash(&lang);
+            self.export_lang_page(build_dir, cache, &lang, "404.html", hash, || {
+                self.render_not_found(&lang)
+            })?;
         }
         Ok(())
     }
 
-    fn tag_url(&self, name: &str) -> String {
-        format!("/blog/tags/{}.html", &name)
+    /// export the page `name` of language `lang` rendered by `render`, if its `hash` changed
+    ///
+    /// the page of multilingual blog is exported into the language directory, and the page of
+    /// blog language is also exported into the site root. only html pages are minified.
+    fn export_lang_page<F>(&self, build_dir: &Path, cache: &mut BuildCache, lang: &str, name: &str,
+                           hash: u64, render: F) -> Result<()>
+        where F: Fn() -> Result<String>
+    {
+        let mut rel_dests = vec![PathBuf::from(name)];
+        if let Some(dir) = self.lang_dir(lang) {
+            if lang != self.settings.language {
+                rel_dests.clear();
+            }
+            rel_dests.push(Path::new(dir).join(name));
+        }
+        for rel_dest in &rel_dests {
+            cache.add_output(rel_dest);
+        }
+        rel_dests.retain(|x| !cache.is_page_fresh(build_dir, x, hash));
+        if rel_dests.is_empty() {
+            return Ok(());
+        }
+        let content = render()?;
+        for rel_dest in rel_dests {
+            if name.ends_with(".html") {
+                self.write_html(&build_dir.join(&rel_dest), &content)?;
+            } else {
+                create_file(&build_dir.join(&rel_dest))?.write_all(content.as_bytes())?;
+            }
+            cache.set_page(&rel_dest, hash);
+        }
+        Ok(())
+    }
+
+    /// export the atom feed `feed.xml` of every language
+    pub fn export_feeds(&self, build_dir: &Path, cache: &mut BuildCache) -> Result<()> {
+        for lang in self.languages() {
+            let posts: Vec<(u64, PathBuf)> = self.feed_posts(&lang)
+                                                 .iter()
+                                                 .map(|x| (x.hash(), x.dest()))
+                                                 .collect();
+            let hash = hash_of(&posts);
+            self.export_lang_page(build_dir, cache, &lang, "feed.xml", hash, || {
+                self.render_feed(&lang)
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn export_tags(&self, build_dir: &Path, cache: &mut BuildCache) -> Result<()> {
+        let mut stale_tags = Vec::new();
+        for (lang, tags) in &self.tags {
+            let tags_hash = self.tags_hash(lang);
+            for (tag, tag_posts) in tags {
+                let rel_dest = PathBuf::from(self.tag_url(lang, tag).trim_start_matches('/'));
+                let posts = serde_json::to_string(&self.get_posts_maps(lang, tag_posts)?)?;
+                let hash = hash_of(&(tags_hash, posts));
+                cache.add_output(&rel_dest);
+                if !cache.is_page_fresh(build_dir, &rel_dest, hash) {
+                    stale_tags.push((lang, tag, rel_dest, hash));
+                }
+            }
+        }
+        self.pool.install(|| {
+            stale_tags.par_iter()
+                      .map(|&(lang, tag, ref rel_dest, _)| {
+                          let html = self.render_tag(lang, tag)?;
+                          self.write_html(&build_dir.join(rel_dest), &html)
+                      })
+                      .collect::<Result<()>>()
+        })?;
+        for (_, _, rel_dest, hash) in stale_tags {
+            cache.set_page(&rel_dest, hash);
+        }
+        Ok(())
     }
 
-    fn tag_map<T>(&self, name: &str, posts: &Vec<T>) -> Map<String, Value> {
+    /// write `.gz` and `.br` variants of html, css, javascript, xml and json files.
+    pub fn export_compressed(&self, build_dir: &Path, cache: &mut BuildCache) -> Result<()> {
+        debug!("exporting compressed files ...");
+        let sources: Vec<PathBuf> = cache.current_outputs()
+                                         .iter()
+                                         .filter(|x| is_compressible(x))
+                                         .map(|x| build_dir.join(x))
+                                         .collect();
+        let variants = self.pool.install(|| {
+            sources.par_iter()
+                   .map(|src| compress_file(src))
+                   .collect::<Result<Vec<Vec<PathBuf>>>>()
+        })?;
+        for variant in variants.iter().flat_map(|x| x.iter()) {
+            cache.add_output(variant.strip_prefix(build_dir)?);
+        }
+        Ok(())
+    }
+
+    /// write the rendered `html` page into `dest`, minify it if `minify` setting is set
+    fn write_html(&self, dest: &Path, html: &str) -> Result<()> {
+        let mut f = create_file(dest)?;
+        if self.settings.minify {
+            f.write_all(&minify_html(html.as_bytes()))?;
+        } else {
+            f.write_all(html.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// hash of mdblog version and blog settings
+    fn settings_hash(&self) -> Result<u64> {
+        let settings = toml::to_string(&self.settings)?;
+        Ok(hash_of(&(env!("CARGO_PKG_VERSION"), settings)))
+    }
+
+    /// hash of blog data rendered in every page of language `lang`
+    ///
+    /// includes all tags of `lang` with their post numbers, the blog languages, and all posts
+    /// if templates query them by `get_post`, `get_tag` or `url_for` functions, so editing a
+    /// post does not rebuild the other pages unless templates query posts.
+    fn tags_hash(&self, lang: &str) -> u64 {
+        let tags: Vec<(&String, usize)> = self.tags[lang]
+                                              .iter()
+                                              .map(|(name, posts)| (name, posts.len()))
+                                              .collect();
+        let languages = self.languages();
+        let mut posts: Vec<(u64, Option<&str>)> = Vec::new();
+        if self.templates_query_posts(lang) {
+            posts.extend(self.posts.iter().map(|x| (x.hash(), x.lang())));
+        }
+        hash_of(&(tags, languages, posts))
+    }
+
+    /// whether any template of language `lang` calls the functions querying posts
+    fn templates_query_posts(&self, lang: &str) -> bool {
+        self.renderers[lang]
+            .templates
+            .values()
+            .any(|x| calls_function(x, &["get_post", "get_tag", "url_for"]))
+    }
+
+    fn tag_url(&self, lang: &str, name: &str) -> String {
+        tag_url(self.lang_dir(lang), name)
+    }
+
+    fn tag_map<T>(&self, lang: &str, name: &str, posts: &Vec<T>) -> Map<String, Value> {
         let mut map = Map::new();
         map.insert("name".to_string(), Value::String(name.to_string()));
         let tag_len = format!("{:?}", &posts.len());
         map.insert("num".to_string(), Value::String(tag_len));
-        map.insert("url".to_string(), Value::String(self.tag_url(&name)));
+        map.insert("url".to_string(), Value::String(self.tag_url(lang, &name)));
+        map
+    }
+
+    /// the posts of language `lang`
+    fn lang_posts(&self, lang: &str) -> Vec<Arc<Post>> {
+        self.posts.iter().filter(|x| self.post_lang(x) == lang).cloned().collect()
+    }
+
+    /// the link of page translated into language `lang`
+    fn translation_map(&self, lang: &str, url: String) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert("lang".to_string(), Value::String(lang.to_string()));
+        map.insert("url".to_string(), Value::String(url));
         map
     }
 
-    pub fn get_base_context(&self, title: &str) -> Result<Context> {
+    /// the links of all posts with the same key as `post`, `post` itself included
+    fn post_translations(&self, post: &Post) -> Vec<Map<String, Value>> {
+        if !self.is_multilingual() {
+            return Vec::new();
+        }
+        let mut translations: Vec<&Arc<Post>> =
+            self.posts.iter().filter(|x| x.key() == post.key()).collect();
+        translations.sort_by_key(|x| x.lang());
+        translations.iter()
+                    .map(|x| {
+                        let url = x.url().to_string_lossy().replace('\\', "/");
+                        let mut map = self.translation_map(self.post_lang(x), url);
+                        map.insert("title".to_string(), Value::String(x.title().to_string()));
+                        map
+                    })
+                    .collect()
+    }
+
+    /// the links of the language page `name` in all languages, e.g. `index.html`
+    fn lang_page_translations(&self, name: &str) -> Vec<Map<String, Value>> {
+        self.settings
+            .languages
+            .iter()
+            .map(|x| self.translation_map(x, format!("/{}/{}", x, name)))
+            .collect()
+    }
+
+    /// the links of tag page `tag` in all languages with the same tag
+    fn tag_translations(&self, tag: &str) -> Vec<Map<String, Value>> {
+        if !self.is_multilingual() {
+            return Vec::new();
+        }
+        self.tags
+            .iter()
+            .filter(|&(_, tags)| tags.contains_key(tag))
+            .map(|(x, _)| self.translation_map(x, self.tag_url(x, tag)))
+            .collect()
+    }
+
+    /// base context of pages in language `lang`, `translations` are the links of the page in
+    /// all languages
+    pub fn get_base_context(&self,
+                            lang: &str,
+                            title: &str,
+                            translations: &[Map<String, Value>])
+                            -> Result<Context> {
         let mut context = Context::new();
         context.add("title", &title);
         context.add("site_logo", &self.settings.site_logo);
         context.add("site_name", &self.settings.site_name);
         context.add("site_motto", &self.settings.site_motto);
         context.add("footer_note", &self.settings.footer_note);
+        context.add("language", &lang);
+        context.add("home_url", &self.lang_url(lang, "index.html"));
+        context.add("feed_url", &self.lang_url(lang, "feed.xml"));
+        context.add("translations", &translations);
+        context.add("theme_options", self.theme.options());
         let mut all_tags = Vec::new();
-        for (tag_key, tag_posts) in &self.tags {
-            all_tags.push(self.tag_map(&tag_key, &tag_posts));
+        for (tag_key, tag_posts) in &self.tags[lang] {
+            all_tags.push(self.tag_map(lang, &tag_key, &tag_posts));
         }
         all_tags.sort_by(|a, b| {
                              a.get("name").unwrap()
@@ -426,64 +949,176 @@ impl Mdblog {
 
     pub fn render_post(&self, post: &Post) -> Result<String> {
         debug!("rendering post({}) ...", post.path.display());
-        let mut context = self.get_base_context(&post.title())?;
+        let lang = self.post_lang(post);
+        let translations = self.post_translations(post);
+        let mut context = self.get_base_context(lang, &post.title(), &translations)?;
         context.add("content", &post.content());
         let mut post_tags = Vec::new();
         if !post.is_hidden() {
             context.add("datetime",
-                        &post.datetime().format("%Y-%m-%d %H:%M:%S").to_string());
+                        &self.i18n[lang].format_datetime(&post.datetime(),
+                                                         "datetime_format",
+                                                         "%Y-%m-%d %H:%M:%S"));
             for tag_key in post.tags() {
-                let tag_posts = self.tags.get(tag_key)
+                let tag_posts = self.tags[lang].get(tag_key)
                                     .expect(&format!("post tag({}) does not add to blog tags",
                                                      tag_key));
-                post_tags.push(self.tag_map(&tag_key, &tag_posts));
+                post_tags.push(self.tag_map(lang, &tag_key, &tag_posts));
             }
         } else {
             context.add("datetime", &"".to_string());
         }
 
         context.add("post_tags", &post_tags);
-        Ok(self.renderer.render("post.tpl", &context)?)
+        let template = self.post_template(post);
+        let renderer = &self.renderers[lang];
+        if !renderer.templates.contains_key(template) {
+            return Err(Error::PostTemplateNotFound(post.path.clone(),
+                                                   template.to_string(),
+                                                   self.theme.name.clone()));
+        }
+        renderer.render(template, &context).map_err(|why| {
+            match Error::from(why) {
+                Error::Template(msg) => {
+                    Error::PostRender(post.path.clone(), template.to_string(), msg)
+                },
+                err => err,
+            }
+        })
+    }
+
+    /// the template of `post`
+    ///
+    /// from the `template` header of post, or the default template of the nearest directory in
+    /// `post_templates` settings, or `post.tpl`.
+    fn post_template<'a>(&'a self, post: &'a Post) -> &'a str {
+        if let Some(template) = post.template() {
+            return template;
+        }
+        let rel_path = post.path.strip_prefix("posts").unwrap_or(&post.path);
+        self.settings
+            .post_templates
+            .iter()
+            .filter(|x| rel_path.starts_with(&x.dir))
+            .max_by_key(|x| Path::new(&x.dir).components().count())
+            .map(|x| x.template.as_str())
+            .unwrap_or("post.tpl")
     }
 
-    pub fn render_index(&self) -> Result<String> {
-        debug!("rendering index ...");
-        let mut context = self.get_base_context(&self.settings.site_name)?;
-        context.add("posts", &self.get_posts_maps(&self.posts)?);
-        Ok(self.renderer.render("index.tpl", &context)?)
+    pub fn render_index(&self, lang: &str) -> Result<String> {
+        debug!("rendering index({}) ...", lang);
+        let mut context = self.get_base_context(lang,
+                                                &self.settings.site_name,
+                                                &self.lang_page_translations("index.html"))?;
+        context.add("posts", &self.get_posts_maps(lang, &self.lang_posts(lang))?);
+        self.renderers[lang]
+            .render("index.tpl", &context)
+            .map_err(|why| template_error("index.tpl", why))
     }
 
-    fn get_posts_maps(&self, posts: &Vec<Rc<Post>>) -> Result<Vec<Map<String, Value>>> {
+    pub fn render_not_found(&self, lang: &str) -> Result<String> {
+        debug!("rendering not found page({}) ...", lang);
+        let context = self.get_base_context(lang,
+                                            &self.i18n[lang].translate("not_found"),
+                                            &self.lang_page_translations("404.html"))?;
+        self.renderers[lang]
+            .render("404.tpl", &context)
+            .map_err(|why| template_error("404.tpl", why))
+    }
+
+    fn get_posts_maps(&self,
+                      lang: &str,
+                      posts: &Vec<Arc<Post>>)
+                      -> Result<Vec<Map<String, Value>>> {
         let mut maps = Vec::new();
         for post in posts.iter().filter(|p| !p.is_hidden()) {
-            maps.push(post.map());
+            maps.push(post.map(&self.i18n[lang]));
         }
         Ok(maps)
     }
 
-    pub fn render_tag(&self, tag: &str) -> Result<String> {
+    pub fn render_tag(&self, lang: &str, tag: &str) -> Result<String> {
         debug!("rendering tag({}) ...", tag);
-        let mut context = self.get_base_context(&tag)?;
-        let posts = self.tags
+        let mut context = self.get_base_context(lang, &tag, &self.tag_translations(tag))?;
+        let posts = self.tags[lang]
                         .get(tag)
                         .expect(&format!("get tag({}) error", &tag));
-        context.add("posts", &self.get_posts_maps(&posts)?);
-        Ok(self.renderer.render("tag.tpl", &context)?)
+        context.add("posts", &self.get_posts_maps(lang, &posts)?);
+        self.renderers[lang]
+            .render("tag.tpl", &context)
+            .map_err(|why| template_error("tag.tpl", why))
     }
 
-    pub fn list_blog_theme(&self) -> Result<()> {
-        let theme_root = self.root.join("_themes");
-        if !theme_root.exists() || !theme_root.is_dir() {
-            error!("no theme");
+    /// render the atom feed of the latest posts of language `lang`
+    ///
+    /// the links are absolute if `site_url` setting is set.
+    pub fn render_feed(&self, lang: &str) -> Result<String> {
+        debug!("rendering feed({}) ...", lang);
+        let site_url = self.settings.site_url.trim_end_matches('/');
+        let home_url = escape_xml(&format!("{}{}", site_url, self.lang_url(lang, "index.html")));
+        let feed_url = escape_xml(&format!("{}{}", site_url, self.lang_url(lang, "feed.xml")));
+        let posts = self.feed_posts(lang);
+        let updated = match posts.first() {
+            Some(post) => post.datetime(),
+            None => Local.timestamp(0, 0),
+        };
+        let mut feed = String::new();
+        feed.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        feed.push_str(&format!("<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"{}\">\n",
+                               escape_xml(lang)));
+        feed.push_str(&format!("  <title>{}</title>\n", escape_xml(&self.settings.site_name)));
+        feed.push_str(&format!("  <link href=\"{}\" rel=\"self\"/>\n", feed_url));
+        feed.push_str(&format!("  <link href=\"{}\"/>\n", home_url));
+        feed.push_str(&format!("  <id>{}</id>\n", home_url));
+        feed.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+        for post in posts {
+            let url = escape_xml(&format!("{}{}", site_url, post.url().display()));
+            feed.push_str("  <entry>\n");
+            feed.push_str(&format!("    <title>{}</title>\n", escape_xml(post.title())));
+            feed.push_str(&format!("    <link href=\"{}\"/>\n", url));
+            feed.push_str(&format!("    <id>{}</id>\n", url));
+            feed.push_str(&format!("    <updated>{}</updated>\n", post.datetime().to_rfc3339()));
+            feed.push_str(&format!("    <content type=\"html\">{}</content>\n",
+                                   escape_xml(&post.content())));
+            feed.push_str("  </entry>\n");
         }
-        for entry in std::fs::read_dir(theme_root)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                info!("* {}", path.file_name()
-                                  .expect("theme name error")
-                                  .to_str()
-                                  .expect("theme name error"));
+        feed.push_str("</feed>\n");
+        Ok(feed)
+    }
+
+    /// the latest posts of language `lang` in its feed
+    fn feed_posts(&self, lang: &str) -> Vec<Arc<Post>> {
+        self.lang_posts(lang).into_iter().filter(|x| !x.is_hidden()).take(FEED_SIZE).collect()
+    }
+
+    pub fn list_blog_theme(&self) -> Result<()> {
+        for (name, manifest) in Theme::list(&self.root)? {
+            let mut line = format!("* {}", name);
+            if let Some(ref version) = manifest.version {
+                line.push_str(&format!(" {}", version));
+            }
+            if let Some(ref author) = manifest.author {
+                line.push_str(&format!(" by {}", author));
+            }
+            if let Some(ref extends) = manifest.extends {
+                line.push_str(&format!(", extends {}", extends));
+            }
+            if self.settings.theme == name {
+                line.push_str(" (in use)");
+            }
+            if let Some(ref description) = manifest.description {
+                line.push_str(&format!(": {}", description));
+            }
+            info!("{}", line);
+            for (key, option) in &manifest.options {
+                let mut line = format!("    {}: {:?}", key, option.kind);
+                if let Some(ref default) = option.default {
+                    line.push_str(&format!(" = {}", default));
+                }
+                if let Some(ref description) = option.description {
+                    line.push_str(&format!(", {}", description));
+                }
+                info!("{}", line);
             }
         }
         Ok(())
@@ -494,6 +1129,155 @@ impl Mdblog {
         Ok(())
     }
 
+    pub fn install_blog_theme(&self, source: &str) -> Result<()> {
+        let name = install_theme(&self.root, source)?;
+        if let Err(why) = self.validate_blog_theme(&name) {
+            std::fs::remove_dir_all(self.root.join("_themes").join(&name))?;
+            return Err(why);
+        }
+        info!("theme({}) installed", name);
+        Ok(())
+    }
+
+    pub fn update_blog_theme(&self, name: &str) -> Result<()> {
+        update_theme(&self.root, name, || self.validate_blog_theme(name))?;
+        info!("theme({}) updated", name);
+        Ok(())
+    }
+
+    /// validate theme `name` can be loaded and its templates can be compiled
+    fn validate_blog_theme(&self, name: &str) -> Result<()> {
+        let theme = Mdblog::get_theme(&self.root, name)?;
+        Mdblog::get_renderer(&theme, &self.settings, &self.settings.language)?;
+        Ok(())
+    }
+
+    /// check theme `name` by rendering its page templates with sample posts
+    ///
+    /// the other top-level templates, which are not extended by any template, are rendered as
+    /// post templates, e.g. `slides.tpl`.
+    /// reports template errors(e.g. undefined variables), blocks not defined in parent templates
+    /// and references to missing static files, the build directory is not touched.
+    pub fn check_blog_theme(&self, name: &str) -> Result<()> {
+        let mut mb = Mdblog::new(&self.root)?;
+        mb.settings = self.settings.clone();
+        mb.settings.theme = name.to_string();
+        if name != self.settings.theme {
+            mb.settings.theme_options.clear();
+        }
+        mb.load_theme()?;
+        let mut posts = Vec::new();
+        for &(path, content) in SAMPLE_POSTS {
+            let mut post = Post::new(&self.root, &Path::new("posts").join(path));
+            post.parse(content)?;
+            posts.push(post);
+        }
+        mb.set_posts(posts);
+
+        let mut problems = mb.check_templates();
+        let statics: Vec<String> = mb.theme
+                                     .static_files(mb.settings.minify)?
+                                     .iter()
+                                     .map(|x| x.to_string_lossy().replace('\\', "/"))
+                                     .map(|x| format!("/{}", x))
+                                     .collect();
+        let lang = &mb.settings.language;
+        let mut pages = vec![("index.tpl", mb.render_index(lang))];
+        pages.extend(mb.posts.iter().map(|post| ("post.tpl", mb.render_post(post))));
+        pages.extend(mb.tags[lang].keys().map(|tag| ("tag.tpl", mb.render_tag(lang, tag))));
+        if mb.renderers[lang].templates.contains_key("404.tpl") {
+            pages.push(("404.tpl", mb.render_not_found(lang)));
+        }
+        let renderer = &mb.renderers[lang];
+        let extended: Vec<&String> = renderer.templates
+                                             .values()
+                                             .flat_map(|x| x.parents.iter())
+                                             .collect();
+        let page_templates = ["index.tpl", "post.tpl", "tag.tpl", "404.tpl"];
+        let mut post_templates: Vec<&String> =
+            renderer.templates
+                    .keys()
+                    .filter(|x| !x.contains('/') && !x.starts_with('_') && !extended.contains(x))
+                    .filter(|x| !page_templates.contains(&x.as_str()))
+                    .collect();
+        post_templates.sort();
+        for template in post_templates {
+            let (path, content) = SAMPLE_POSTS[0];
+            let mut post = Post::new(&self.root, &Path::new("posts").join(path));
+            post.parse(&format!("template: {}\n{}", template, content))?;
+            pages.push((template.as_str(), mb.render_post(&post)));
+        }
+        for (template, page) in pages {
+            let found = match page {
+                Ok(html) => broken_static_refs(template, &html, &statics),
+                Err(why) => vec![format!("{}: {}", template, why)],
+            };
+            for problem in found {
+                if !problems.contains(&problem) {
+                    problems.push(problem);
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            info!("theme({}) is ok", name);
+            return Ok(());
+        }
+        for problem in &problems {
+            warn!("{}", problem);
+        }
+        Err(Error::ThemeInvalid(name.to_string(), format!("{} problems found", problems.len())))
+    }
+
+    /// problems of templates: missing page templates and blocks not defined in parent templates
+    fn check_templates(&self) -> Vec<String> {
+        let renderer = &self.renderers[&self.settings.language];
+        let mut problems = Vec::new();
+        for name in &["index.tpl", "post.tpl", "tag.tpl"] {
+            if !renderer.templates.contains_key(*name) {
+                problems.push(format!("template {} is missing", name));
+            }
+        }
+        let mut names: Vec<&String> = renderer.templates.keys().collect();
+        names.sort();
+        for name in names {
+            let template = &renderer.templates[name];
+            if template.parents.is_empty() {
+                continue;
+            }
+            for node in &template.ast {
+                let block = match *node {
+                    Node::Block(_, ref block, _) => &block.name,
+                    _ => continue,
+                };
+                let defined = template.parents.iter().any(|parent| {
+                    renderer.templates
+                        .get(parent)
+                        .map(|x| x.blocks.contains_key(block))
+                        .unwrap_or(false)
+                });
+                if !defined {
+                    problems.push(format!("{}: block `{}` is not defined in {}",
+                                          name, block, template.parents.join(", ")));
+                }
+            }
+        }
+        problems
+    }
+
+    pub fn diff_blog_theme(&self, name: Option<&str>) -> Result<()> {
+        let name = name.unwrap_or(&self.settings.theme);
+        print!("{}", diff_theme(&self.root, name)?);
+        Ok(())
+    }
+
+    pub fn upgrade_blog_theme(&self, name: Option<&str>) -> Result<()> {
+        let name = name.unwrap_or(&self.settings.theme);
+        upgrade_theme(&self.root, name)?;
+        info!("theme({}) upgraded", name);
+        Ok(())
+    }
+
     pub fn delete_blog_theme(&self, name: &str) -> Result<()> {
         if self.settings.theme == name {
             return Err(Error::ThemeInUse(name.to_string()));
@@ -507,10 +1291,7 @@ impl Mdblog {
     }
 
     pub fn set_blog_theme(&mut self, name: &str) -> Result<()> {
-        let theme_path = self.root.join("_themes").join(name);
-        if !theme_path.exists() || !theme_path.is_dir() {
-            return Err(Error::ThemeNotFound(name.to_string()));
-        }
+        self.validate_blog_theme(name)?;
         self.settings.theme = name.to_string();
         self.export_config()?;
         Ok(())
@@ -524,6 +1305,56 @@ fn is_hidden(entry: &DirEntry) -> bool {
          .unwrap_or(false)
 }
 
+/// whether `rel_path` or any of its ancestors matches the preserved patterns
+fn is_preserved(rel_path: &Path, patterns: &[Pattern]) -> bool {
+    rel_path.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| patterns.iter().any(|pat| pat.matches_path(p)))
+}
+
+/// references of `html` rendered from `template` to static files not in `statics`
+fn broken_static_refs(template: &str, html: &str, statics: &[String]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for attr in &["src=\"", "href=\""] {
+        for part in html.split(attr).skip(1) {
+            let url = part.split('"').next().unwrap_or("");
+            let path = url.split(|c| c == '?' || c == '#').next().unwrap_or("");
+            if path.starts_with("/static/") && !statics.iter().any(|x| x == path) {
+                problems.push(format!("{}: static file {} not found", template, path));
+            }
+        }
+    }
+    problems
+}
+
+/// the error of parsing or rendering template `name`
+fn template_error(name: &str, err: ::tera::Error) -> Error {
+    match Error::from(err) {
+        Error::Template(msg) => Error::TemplateFile(name.to_string(), msg),
+        err => err,
+    }
+}
+
+/// escape the xml special characters of `text`
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// whether `path` is a css or scss file
+fn is_stylesheet(path: &Path) -> bool {
+    path.extension().map(|x| x == "css" || x == "scss").unwrap_or(false)
+}
+
 fn is_markdown_file(entry: &DirEntry) -> bool {
     if !entry.path().is_file() {
         return false;
@@ -545,5 +1376,180 @@ fn is_markdown_file(entry: &DirEntry) -> bool {
     }
 }
 
+/// number of the latest posts in feeds
+const FEED_SIZE: usize = 20;
+
 static HELLO_POST: &'static [u8] = include_bytes!("post/hello.md");
 static MATH_POST: &'static [u8] = include_bytes!("post/math.md");
+
+/// sample posts for checking themes
+static SAMPLE_POSTS: &'static [(&'static str, &'static str)] = &[
+    ("hello.md", include_str!("post/hello.md")),
+    ("math.md", include_str!("post/math.md")),
+    ("hidden.md", "date: 1970-01-01 00:00:00\ntags: hidden\nhidden: true\n\nhidden post\n"),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    use post::Post;
+    use settings::PostTemplate;
+    use super::Mdblog;
+
+    /// a temporary blog root directory
+    fn test_root(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("mdblog-lib-{}-{}", process::id(), name));
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn build_restores_damaged_static_files() {
+        let root = test_root("static").join("blog");
+        let mut mb = Mdblog::new(&root).unwrap();
+        mb.init().unwrap();
+        mb.load().unwrap();
+        mb.build().unwrap();
+        let css = root.join("_build/static/main.css");
+        let js = root.join("_build/static/main.js");
+        let origin_js = fs::read(&js).unwrap();
+        fs::remove_file(&css).unwrap();
+        fs::write(&js, "damaged").unwrap();
+
+        mb.build().unwrap();
+        assert!(css.exists());
+        assert_eq!(fs::read(&js).unwrap(), origin_js);
+        fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    /// modification time of file `path`
+    fn modified(path: &Path) -> ::std::time::SystemTime {
+        fs::metadata(path).unwrap().modified().unwrap()
+    }
+
+    #[test]
+    fn editing_post_leaves_other_pages_alone() {
+        let root = test_root("incremental").join("blog");
+        let mut mb = Mdblog::new(&root).unwrap();
+        mb.init().unwrap();
+        mb.load().unwrap();
+        mb.build().unwrap();
+        let hello = root.join("_build/blog/posts/hello.html");
+        let math = root.join("_build/blog/posts/math.html");
+        let (hello_modified, math_modified) = (modified(&hello), modified(&math));
+
+        let math_post = root.join("posts/math.md");
+        let content = fs::read_to_string(&math_post).unwrap();
+        fs::write(&math_post, format!("{}\nedited\n", content)).unwrap();
+        mb.load().unwrap();
+        mb.build().unwrap();
+        assert_eq!(modified(&hello), hello_modified);
+        assert_ne!(modified(&math), math_modified);
+
+        // templates querying posts depend on every post
+        let template = "{% extends \"base.tpl\" %}\n\
+                        {% block main %}{{ url_for(path=\"posts/math.md\") }}{% endblock main %}";
+        fs::create_dir_all(root.join("templates")).unwrap();
+        fs::write(root.join("templates/post.tpl"), template).unwrap();
+        let mut mb = Mdblog::new(&root).unwrap();
+        mb.load().unwrap();
+        mb.build().unwrap();
+        let hello_modified = modified(&hello);
+        fs::write(&math_post, content).unwrap();
+        mb.load().unwrap();
+        mb.build().unwrap();
+        assert_ne!(modified(&hello), hello_modified);
+        fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn exports_feed_of_every_language() {
+        let root = test_root("feeds").join("blog");
+        let mut mb = Mdblog::new(&root).unwrap();
+        mb.init().unwrap();
+        fs::write(root.join("posts/hello.zh.md"),
+                  "date: 2018-01-01 00:00:00\ntags: hello\n\n<你好> & 世界").unwrap();
+        fs::write(root.join("posts/hidden.zh.md"),
+                  "date: 2018-01-02 00:00:00\nhidden: true\n\nhidden").unwrap();
+        mb.settings.languages = vec!["en".to_string(), "zh".to_string()];
+        mb.settings.site_url = "https://example.com/".to_string();
+        mb.load_theme().unwrap();
+        mb.load().unwrap();
+        mb.build().unwrap();
+
+        let feed = |path: &str| fs::read_to_string(root.join("_build").join(path)).unwrap();
+        let zh = feed("zh/feed.xml");
+        assert!(zh.contains("<link href=\"https://example.com/zh/feed.xml\" rel=\"self\"/>"));
+        assert!(zh.contains("<id>https://example.com/zh/blog/posts/hello.html</id>"));
+        assert!(zh.contains("&amp;lt;你好&amp;gt; &amp;amp; 世界"));
+        assert!(!zh.contains("hidden"));
+        assert!(!zh.contains("/en/"));
+        let en = feed("en/feed.xml");
+        assert!(en.contains("<id>https://example.com/en/blog/posts/math.html</id>"));
+        assert!(!en.contains("/zh/"));
+        assert_eq!(feed("feed.xml"), en);
+        fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn post_template_prefers_header_then_nearest_directory() {
+        let root = test_root("post-template");
+        let mut mb = Mdblog::new(&root).unwrap();
+        for &(dir, template) in &[("slides", "slides.tpl"), ("slides/deep", "deep.tpl")] {
+            mb.settings.post_templates.push(PostTemplate {
+                dir: dir.to_string(),
+                template: template.to_string(),
+            });
+        }
+        let cases = [("a.md", "", "post.tpl"),
+                     ("a.md", "template: custom.tpl\n", "custom.tpl"),
+                     ("slides/b.md", "", "slides.tpl"),
+                     ("slides/b.md", "template: custom.tpl\n", "custom.tpl"),
+                     ("slides/deep/c.md", "", "deep.tpl"),
+                     ("slides/deeper/d.md", "", "slides.tpl"),
+                     ("slidesx/e.md", "", "post.tpl")];
+        for &(path, header, template) in &cases {
+            let mut post = Post::new(&root, &Path::new("posts").join(path));
+            post.parse(&format!("date: 1970-01-01 00:00:00\n{}\nbody", header)).unwrap();
+            assert_eq!(mb.post_template(&post), template, "{}", path);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn clean_keeps_preserved_paths_only() {
+        let root = test_root("clean");
+        let build_dir = root.join("_build");
+        for path in &[".git/config",
+                      ".git/objects/ab/cd",
+                      "CNAME",
+                      ".mdblog-cache.json",
+                      "404.html",
+                      "index.html",
+                      "blog/posts/hello.html",
+                      "static/main.css"] {
+            let path = build_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        Mdblog::new(&root).unwrap().clean().unwrap();
+
+        let mut left: Vec<String> = Vec::new();
+        for entry in ::walkdir::WalkDir::new(&build_dir).min_depth(1) {
+            let entry = entry.unwrap();
+            let path = entry.path().strip_prefix(&build_dir).unwrap();
+            left.push(path.to_string_lossy().into_owned());
+        }
+        left.sort();
+        assert_eq!(left, vec![".git", ".git/config", ".git/objects", ".git/objects/ab",
+                              ".git/objects/ab/cd", "CNAME"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
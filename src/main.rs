@@ -32,13 +32,23 @@ enum Opt {
     },
     #[structopt(name = "build")]
     /// Build the blog static files
-    Build,
+    Build {
+        #[structopt(short = "j", long = "jobs", default_value = "0")]
+        /// Number of threads rendering pages, 0 means the number of CPUs
+        jobs: usize,
+    },
+    #[structopt(name = "clean")]
+    /// Remove the blog static files, except the preserved paths
+    Clean,
     #[structopt(name = "serve")]
     /// Serve the blog, rebuild on change
     Serve {
         #[structopt(short = "p", long = "port", default_value = "5000")]
         /// Serve the blog at http://127.0.0.1:<port>
         port: u16,
+        #[structopt(short = "j", long = "jobs", default_value = "0")]
+        /// Number of threads rendering pages, 0 means the number of CPUs
+        jobs: usize,
     },
     #[structopt(name = "theme")]
     /// Blog theme operations
@@ -56,6 +66,36 @@ enum SubCommandTheme {
     New {
         /// theme name
         name: String,
+    },
+    #[structopt(name = "install")]
+    /// Install a theme from a `.tar.gz`/`.zip` archive or a git repository
+    Install {
+        /// archive file path, or git repository path or url
+        source: String,
+    },
+    #[structopt(name = "update")]
+    /// Update a theme installed from a git repository
+    Update {
+        /// theme name
+        name: String,
+    },
+    #[structopt(name = "check")]
+    /// Check a theme by rendering its templates with sample posts
+    Check {
+        /// theme name
+        name: String,
+    },
+    #[structopt(name = "diff")]
+    /// Show differences between a theme and its built-in origin
+    Diff {
+        /// theme name, default is the theme in use
+        name: Option<String>,
+    },
+    #[structopt(name = "upgrade")]
+    /// Upgrade a theme to the current version of its built-in origin
+    Upgrade {
+        /// theme name, default is the theme in use
+        name: Option<String>,
     },
      #[structopt(name = "delete")]
     /// Delete a theme
@@ -81,8 +121,9 @@ fn main() {
     let res = match opt {
         Opt::Init {ref name} => init(name),
         Opt::New {ref tags, ref path} => new(path, tags),
-        Opt::Build => build(),
-        Opt::Serve { port } => serve(port),
+        Opt::Build { jobs } => build(jobs),
+        Opt::Clean => clean(),
+        Opt::Serve { port, jobs } => serve(port, jobs),
         Opt::Theme(ref subcmd) => theme(subcmd),
     };
 
@@ -107,21 +148,29 @@ fn new(path: &Path, tags: &Vec<String>) -> Result<()> {
     Ok(())
 }
 
-fn build() -> Result<()> {
+fn build(jobs: usize) -> Result<()> {
     let root_dir = env::current_dir()?;
     let mut mb = Mdblog::new(&root_dir)?;
     mb.load_customize_settings()?;
+    mb.set_jobs(jobs)?;
     mb.load()?;
     mb.build()?;
     Ok(())
 }
 
-fn serve(port: u16) -> Result<()> {
+fn clean() -> Result<()> {
     let root_dir = env::current_dir()?;
     let mut mb = Mdblog::new(&root_dir)?;
     mb.load_customize_settings()?;
-    mb.load()?;
-    mb.build()?;
+    mb.clean()?;
+    Ok(())
+}
+
+fn serve(port: u16, jobs: usize) -> Result<()> {
+    let root_dir = env::current_dir()?;
+    let mut mb = Mdblog::new(&root_dir)?;
+    mb.load_customize_settings()?;
+    mb.set_jobs(jobs)?;
     mb.serve(port)?;
     Ok(())
 }
@@ -134,6 +183,15 @@ fn theme(cmd: &SubCommandTheme) -> Result<()> {
     match cmd {
         &SubCommandTheme::List => mb.list_blog_theme()?,
         &SubCommandTheme::New { ref name } => mb.create_blog_theme(name)?,
+        &SubCommandTheme::Install { ref source } => mb.install_blog_theme(source)?,
+        &SubCommandTheme::Update { ref name } => mb.update_blog_theme(name)?,
+        &SubCommandTheme::Check { ref name } => mb.check_blog_theme(name)?,
+        &SubCommandTheme::Diff { ref name } => {
+            mb.diff_blog_theme(name.as_ref().map(|x| x.as_str()))?
+        },
+        &SubCommandTheme::Upgrade { ref name } => {
+            mb.upgrade_blog_theme(name.as_ref().map(|x| x.as_str()))?
+        },
         &SubCommandTheme::Delete { ref name } => mb.delete_blog_theme(name)?,
         &SubCommandTheme::Set { ref name } => mb.set_blog_theme(name)?,
     }
@@ -0,0 +1,53 @@
+use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
+use minify_html::{self, Cfg};
+use minify_js::{self, Session, TopLevelMode};
+
+/// minify html page, content of `<pre>` and `<textarea>` is preserved
+pub fn minify_html(html: &[u8]) -> Vec<u8> {
+    let mut cfg = Cfg::spec_compliant();
+    cfg.keep_closing_tags = true;
+    cfg.keep_html_and_head_opening_tags = true;
+    cfg.minify_css = true;
+    cfg.minify_js = true;
+    minify_html::minify(html, &cfg)
+}
+
+/// minify css stylesheet, return the origin content if parsing failed
+pub fn minify_css(css: &[u8]) -> Vec<u8> {
+    let source = match ::std::str::from_utf8(css) {
+        Ok(s) => s,
+        Err(_) => return css.to_vec(),
+    };
+    let mut stylesheet = match StyleSheet::parse(source, ParserOptions::default()) {
+        Ok(stylesheet) => stylesheet,
+        Err(why) => {
+            warn!("minify css error: {}", why);
+            return css.to_vec();
+        },
+    };
+    if let Err(why) = stylesheet.minify(MinifyOptions::default()) {
+        warn!("minify css error: {}", why);
+        return css.to_vec();
+    }
+    let opts = PrinterOptions { minify: true, ..PrinterOptions::default() };
+    match stylesheet.to_css(opts) {
+        Ok(res) => res.code.into_bytes(),
+        Err(why) => {
+            warn!("minify css error: {}", why);
+            css.to_vec()
+        },
+    }
+}
+
+/// minify javascript, return the origin content if parsing failed
+pub fn minify_js(js: &[u8]) -> Vec<u8> {
+    let session = Session::new();
+    let mut output = Vec::new();
+    match minify_js::minify(&session, TopLevelMode::Global, js, &mut output) {
+        Ok(_) => output,
+        Err(why) => {
+            warn!("minify js error: {:?}", why);
+            js.to_vec()
+        },
+    }
+}
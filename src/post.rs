@@ -8,7 +8,9 @@ use chrono::{DateTime, Local, TimeZone};
 use pulldown_cmark::{html, Options, Parser, OPTION_ENABLE_TABLES};
 use serde_json::{Map, Value};
 
+use cache::hash_of;
 use errors::{Error, Result};
+use i18n::I18n;
 
 /// blog post object
 ///
@@ -20,6 +22,8 @@ use errors::{Error, Result};
 /// * date: the publish datetime, required, `date: 1970-01-01 00:00:00`
 /// * tags: the tags of blog post, required, `tags: hello, world`
 /// * hidden: whether hidden blog post or not, optional, default `true`, `hidden: false`
+/// * template: the template rendering the post, optional, `template: slides.tpl`
+/// * lang: the language of post on multilingual site, optional, `lang: zh`
 pub struct Post {
     /// root path of blog
     root: PathBuf,
@@ -31,6 +35,10 @@ pub struct Post {
     body: String,
     /// headers from parsing the post origin head part
     metadata: HashMap<String, String>,
+    /// post language on multilingual site
+    lang: Option<String>,
+    /// post path without the language suffix, shared by the translations of post
+    key: PathBuf,
 }
 
 impl Post {
@@ -41,6 +49,8 @@ impl Post {
             head: String::new(),
             body: String::new(),
             metadata: HashMap::new(),
+            lang: None,
+            key: path.as_ref().to_owned(),
         }
     }
 
@@ -50,18 +60,24 @@ impl Post {
     }
 
     /// the absolute path of blog post html file
+    ///
+    /// the html file of post on multilingual site is in its language directory.
     pub fn dest(&self) -> PathBuf {
-        Path::new("blog").join(&self.path).with_extension("html")
+        let dest = Path::new("blog").join(&self.key).with_extension("html");
+        match self.lang {
+            Some(ref lang) => Path::new(lang).join(dest),
+            None => dest,
+        }
     }
 
     /// the post url
     pub fn url(&self) -> PathBuf {
-        Path::new("/blog").join(&self.path).with_extension("html")
+        Path::new("/").join(self.dest())
     }
 
     /// blog title
     pub fn title(&self) -> &str {
-        self.path
+        self.key
             .file_stem()
             .and_then(|x| x.to_str())
             .expect(&format!("post filename format error: {}", self.path.display()))
@@ -93,14 +109,50 @@ impl Post {
         }
     }
 
+    /// the template rendering the post, from `template` header
+    pub fn template(&self) -> Option<&str> {
+        self.metadata.get("template").map(|x| x.as_str())
+    }
+
+    /// post language on multilingual site
+    pub fn lang(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+
+    /// post path without the language suffix, posts with the same key are translations
+    pub fn key(&self) -> &Path {
+        &self.key
+    }
+
+    /// set post language on multilingual site of `languages`
+    ///
+    /// the language is from the file name suffix, e.g. `foo.zh.md`, or the `lang` header,
+    /// posts without language are in `default` language.
+    pub fn set_language(&mut self, languages: &[String], default: &str) {
+        let stem = self.path.file_stem().and_then(|x| x.to_str()).unwrap_or("").to_string();
+        if let Some(pos) = stem.rfind('.') {
+            if languages.iter().any(|x| x == &stem[pos + 1..]) {
+                self.lang = Some(stem[pos + 1..].to_string());
+                self.key = self.path.with_file_name(format!("{}.md", &stem[..pos]));
+                return;
+            }
+        }
+        self.key = self.path.clone();
+        let lang = match self.metadata.get("lang") {
+            Some(lang) if languages.contains(lang) => lang.clone(),
+            Some(lang) => {
+                warn!("post({}) language {} is not in blog languages, use {}",
+                      self.path.display(), lang, default);
+                default.to_string()
+            },
+            None => default.to_string(),
+        };
+        self.lang = Some(lang);
+    }
+
     /// the rendered html content of post body port
     pub fn content(&self) -> String {
-        let mut opts = Options::empty();
-        opts.insert(OPTION_ENABLE_TABLES);
-        let mut s = String::with_capacity(self.body.len() * 3 / 2);
-        let p = Parser::new_ext(&self.body, opts);
-        html::push_html(&mut s, p);
-        s
+        render_markdown(&self.body)
     }
 
     /// the post tags
@@ -117,14 +169,24 @@ impl Post {
         }
     }
 
-    /// post context for render
-    pub fn map(&self) -> Map<String, Value> {
+    /// hash of post origin head part and body part
+    pub fn hash(&self) -> u64 {
+        hash_of(&(&self.head, &self.body))
+    }
+
+    /// post context for render, `datetime` is formatted by the `date_format` of `i18n`
+    pub fn map(&self, i18n: &I18n) -> Map<String, Value> {
         let mut map = Map::new();
         map.insert("title".to_string(), Value::String(self.title().to_string()));
         map.insert("url".to_string(),
                    Value::String(format!("{}", self.url().display())));
-        map.insert("datetime".to_string(),
-                   Value::String(self.datetime().format("%Y-%m-%d").to_string()));
+        let datetime = i18n.format_datetime(&self.datetime(), "date_format", "%Y-%m-%d");
+        map.insert("datetime".to_string(), Value::String(datetime));
+        map.insert("date".to_string(),
+                   Value::String(self.datetime().format("%Y-%m-%d %H:%M:%S").to_string()));
+        if let Some(lang) = self.lang() {
+            map.insert("lang".to_string(), Value::String(lang.to_string()));
+        }
 
         map
     }
@@ -135,25 +197,79 @@ impl Post {
         let mut pf = File::open(self.src())?;
         let mut content = String::new();
         pf.read_to_string(&mut content)?;
+        self.parse(&content)
+    }
+
+    /// parse post head part and body part from `content`
+    pub fn parse(&mut self, content: &str) -> Result<()> {
         let v: Vec<&str> = content.splitn(2, "\n\n").collect();
         if v.len() != 2 {
             return Err(Error::PostNoBody(self.path.clone()));
         }
         if v[0].trim().is_empty() {
-            return Err(Error::PostHead(self.path.clone()));
+            return Err(Error::PostHead(self.path.clone(), 1));
         }
         if v[1].trim().is_empty() {
             return Err(Error::PostNoBody(self.path.clone()));
         }
         self.head = v[0].to_string();
         self.body = v[1].to_string();
-        for line in self.head.lines() {
+        for (i, line) in self.head.lines().enumerate() {
             let pair: Vec<&str> = line.splitn(2, ':').collect();
             if pair.len() != 2 {
-                return Err(Error::PostHead(self.path.clone()));
+                return Err(Error::PostHead(self.path.clone(), i + 1));
             }
             self.metadata.insert(pair[0].trim().to_owned(), pair[1].trim().to_owned());
         }
         Ok(())
     }
 }
+
+/// render markdown `text` into html
+pub fn render_markdown(text: &str) -> String {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    let mut s = String::with_capacity(text.len() * 3 / 2);
+    let p = Parser::new_ext(text, opts);
+    html::push_html(&mut s, p);
+    s
+}
+
+/// the tag url, in the directory of `lang` on multilingual site
+pub fn tag_url(lang: Option<&str>, name: &str) -> String {
+    match lang {
+        Some(lang) => format!("/{}/blog/tags/{}.html", lang, name),
+        None => format!("/blog/tags/{}.html", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// post `path` with `header`, language set on blog of `en` and `zh`
+    fn post(path: &str, header: &str) -> Post {
+        let mut post = Post::new(Path::new("/blog"), Path::new(path));
+        post.parse(&format!("date: 1970-01-01 00:00:00\n{}\nbody", header)).unwrap();
+        post.set_language(&["en".to_string(), "zh".to_string()], "en");
+        post
+    }
+
+    #[test]
+    fn set_language_from_suffix_header_or_default() {
+        let cases = [("posts/foo.zh.md", "", "zh", "posts/foo.md"),
+                     ("posts/foo.en.md", "lang: zh\n", "en", "posts/foo.md"),
+                     ("posts/foo.md", "lang: zh\n", "zh", "posts/foo.md"),
+                     ("posts/foo.md", "", "en", "posts/foo.md"),
+                     ("posts/foo.md", "lang: fr\n", "en", "posts/foo.md"),
+                     ("posts/v1.2.md", "", "en", "posts/v1.2.md")];
+        for &(path, header, lang, key) in &cases {
+            let post = post(path, header);
+            assert_eq!(post.lang(), Some(lang), "{}", path);
+            assert_eq!(post.key(), Path::new(key), "{}", path);
+        }
+        let post = post("posts/foo.zh.md", "");
+        assert_eq!(post.dest(), Path::new("zh/blog/posts/foo.html"));
+        assert_eq!(post.url(), Path::new("/zh/blog/posts/foo.html"));
+    }
+}
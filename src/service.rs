@@ -1,20 +1,159 @@
-use std::io;
-use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::fs::{self, File};
 use std::thread;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
 use mime_guess::get_mime_type;
 use percent_encoding::percent_decode;
-use futures::sync::oneshot;
+use futures::sync::{mpsc, oneshot};
 use futures::future::{self, Future};
-use hyper::{self, StatusCode};
-use hyper::header::{ContentLength, ContentType};
+use futures::Sink;
+use hyper::{self, Body, Chunk, Method, StatusCode};
+use hyper::header::{AcceptEncoding, AcceptRanges, Allow, CacheControl, CacheDirective,
+                    ContentEncoding, ContentLength, ContentRange, ContentRangeSpec, ContentType,
+                    Encoding, EntityTag, ETag, Headers, HttpDate, IfModifiedSince, IfNoneMatch,
+                    LastModified, Location, Range, RangeUnit, q};
+use compress::variant_path;
+use errors::Error;
+use failure::Fail;
 use hyper::server::{Request, Response, Service};
 
 static NOTFOUND: &[u8] = b"Not Found";
 
+/// size of the chunks of streamed file bodies
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// path of the server-sent events endpoint notifying blog rebuilds
+static EVENTS_PATH: &str = "/__mdblog/events";
+
+/// live reload client script injected into html pages
+static LIVE_RELOAD_JS: &str = include_str!("service/livereload.js");
+
+/// overlay page of build error
+static BUILD_ERROR_HTML: &str = include_str!("service/error.html");
+
 /// Http Service for static file server
 pub struct HttpService {
     pub root: PathBuf,
+    /// notifier of blog rebuilds to the served pages
+    pub reload: LiveReload,
+}
+
+/// sender of the server-sent events stream of a client
+type EventSender = mpsc::Sender<Result<Chunk, hyper::Error>>;
+
+/// notifier of blog rebuilds to the live reload clients
+///
+/// every served html page subscribes to the server-sent events endpoint, and reloads itself on
+/// `reload` event, or reloads its stylesheets on `css` event.
+/// the error of last failed rebuild is kept, and served instead of html pages until the next
+/// successful rebuild.
+#[derive(Clone, Default)]
+pub struct LiveReload {
+    clients: Arc<Mutex<Vec<EventSender>>>,
+    error: Arc<Mutex<Option<BuildError>>>,
+}
+
+/// error of a failed rebuild, rendered as an overlay page by the dev server
+#[derive(Debug, Clone)]
+pub struct BuildError {
+    /// the post failed to load or render
+    post: Option<PathBuf>,
+    /// the line of post failed to parse
+    line: Option<usize>,
+    /// the template failed to render
+    template: Option<String>,
+    /// messages of the error and its causes
+    causes: Vec<String>,
+}
+
+impl<'a> From<&'a Error> for BuildError {
+    fn from(err: &Error) -> BuildError {
+        let (post, line, template) = match *err {
+            Error::PostHead(ref path, line) => (Some(path.clone()), Some(line), None),
+            Error::PostNoBody(ref path) => (Some(path.clone()), None, None),
+            Error::PostTemplateNotFound(ref path, ref template, _) |
+            Error::PostRender(ref path, ref template, _) => {
+                (Some(path.clone()), None, Some(template.clone()))
+            },
+            Error::TemplateFile(ref template, _) => (None, None, Some(template.clone())),
+            _ => (None, None, None),
+        };
+        BuildError {
+            post: post,
+            line: line,
+            template: template,
+            causes: err.causes().map(|x| x.to_string()).collect(),
+        }
+    }
+}
+
+impl BuildError {
+    /// the overlay page of the error
+    fn render(&self) -> String {
+        let mut details = String::new();
+        if let Some(ref post) = self.post {
+            let post = escape_html(&post.to_string_lossy());
+            details.push_str(&format!("<dt>post</dt><dd>{}</dd>", post));
+        }
+        if let Some(line) = self.line {
+            details.push_str(&format!("<dt>line</dt><dd>{}</dd>", line));
+        }
+        if let Some(ref template) = self.template {
+            details.push_str(&format!("<dt>template</dt><dd>{}</dd>", escape_html(template)));
+        }
+        let causes: String = self.causes
+                                 .iter()
+                                 .map(|x| format!("<li>{}</li>", escape_html(x)))
+                                 .collect();
+        BUILD_ERROR_HTML.replace("{details}", &details).replace("{causes}", &causes)
+    }
+}
+
+impl LiveReload {
+    /// the event stream response of a new client
+    fn subscribe(&self) -> Response {
+        let (mut tx, body) = Body::pair();
+        if tx.try_send(Ok(Chunk::from("retry: 1000\n\n"))).is_ok() {
+            self.clients.lock().expect("lock live reload clients error").push(tx);
+        }
+        Response::new()
+            .with_header(ContentType("text/event-stream".parse().expect("parse mime type error")))
+            .with_header(CacheControl(vec![CacheDirective::NoCache]))
+            .with_body(body)
+    }
+
+    /// clear the last build error, and send `event` to all clients
+    pub fn notify(&self, event: &str) {
+        *self.error.lock().expect("lock build error error") = None;
+        self.send(event);
+    }
+
+    /// keep the error of failed rebuild, and reload all clients to show it
+    pub fn fail(&self, err: &Error) {
+        *self.error.lock().expect("lock build error error") = Some(BuildError::from(err));
+        self.send("reload");
+    }
+
+    /// the error of last failed rebuild
+    fn error(&self) -> Option<BuildError> {
+        self.error.lock().expect("lock build error error").clone()
+    }
+
+    /// send `event` to all clients, the disconnected clients are removed
+    fn send(&self, event: &str) {
+        let mut clients = self.clients.lock().expect("lock live reload clients error");
+        let message = format!("event: {}\ndata: {}\n\n", event, event);
+        let senders = ::std::mem::take(&mut *clients);
+        for mut tx in senders {
+            match tx.try_send(Ok(Chunk::from(message.clone()))) {
+                Err(ref why) if why.is_disconnected() => {},
+                _ => clients.push(tx),
+            }
+        }
+        debug!("live reload({}) sent to {} pages", event, clients.len());
+    }
 }
 
 
@@ -25,44 +164,71 @@ impl Service for HttpService {
     type Future = Box<Future<Item=Self::Response, Error=Self::Error>>;
 
     fn call(&self, req: Request) -> Self::Future {
-        let uri_path = percent_decode(req.uri().path().as_bytes()).decode_utf8().unwrap();
-        match local_path_for_request(&self.root, &uri_path) {
+        let is_head = match *req.method() {
+            Method::Get => false,
+            Method::Head => true,
+            _ => {
+                return Box::new(future::ok(Response::new()
+                    .with_status(StatusCode::MethodNotAllowed)
+                    .with_header(Allow(vec![Method::Get, Method::Head]))
+                    .with_header(ContentLength(0))));
+            },
+        };
+        let res: Self::Future = if req.path() == EVENTS_PATH {
+            Box::new(future::ok(self.reload.subscribe()))
+        } else {
+            self.serve_file(&req, is_head)
+        };
+        // the response of `HEAD` request keeps the headers only
+        Box::new(res.map(move |res| if is_head { res.with_body(Body::empty()) } else { res }))
+    }
+}
+
+impl HttpService {
+    /// the response of the local file requested by `req`, read in a worker thread
+    fn serve_file(&self, req: &Request, is_head: bool) -> <HttpService as Service>::Future {
+        let uri_path = percent_decode(req.uri().path().as_bytes()).decode_utf8_lossy().into_owned();
+        let location = directory_redirect(&self.root, req.uri().path(), req.uri().query());
+        if let Some(location) = location {
+            info!("{} -> {}", uri_path, location);
+            let res = Response::new().with_status(StatusCode::MovedPermanently)
+                                     .with_header(Location::new(location));
+            return Box::new(future::ok(res));
+        }
+        match local_path_for_request(&self.root, req.uri().path()) {
             None => {
                 warn!("Not Found {}", uri_path);
-                Box::new(future::ok(not_found_response()))
+                Box::new(future::ok(not_found_response(&self.root, req.uri().path())))
             },
             Some(path) => {
+                let is_html = path.extension().map(|x| x == "html").unwrap_or(false);
+                // the page may be missing because the build failed
+                if let Some(err) = self.reload.error().filter(|_| is_html || !path.exists()) {
+                    warn!("Build Error {}", uri_path);
+                    return Box::new(future::ok(build_error_response(&err)));
+                }
                 info!("{}", uri_path);
+                let root = self.root.clone();
+                let encoding = req.headers()
+                                  .get::<AcceptEncoding>()
+                                  .filter(|_| !is_html)
+                                  .and_then(|accept| compressed_encoding(&root, &path, accept));
+                let request_path = req.uri().path().to_string();
+                let headers = req.headers().clone();
                 let (tx, rx) = oneshot::channel();
                 thread::spawn(move || {
-                    let mut file = match File::open(&path) {
-                        Ok(f) => f,
-                        Err(_) => {
-                            error!("open file error: {}", &path.display());
-                            tx.send(not_found_response()).expect("Send error on open");
-                            return;
-                        },
-                    };
-                    let mut buf: Vec<u8> = Vec::new();
-                    match io::copy(&mut file, &mut buf) {
-                        Ok(_) => {
-                            let mime = get_mime_type(&path.extension()
-                                                          .expect("get file extention error")
-                                                          .to_str()
-                                                          .expect("get str slice error"));
-                            let res = Response::new()
-                                .with_header(ContentLength(buf.len() as u64))
-                                .with_header(ContentType(mime))
-                                .with_body(buf);
-                            tx.send(res)
-                              .expect("Send error on successful file read");
+                    let res = match file_response(&path, encoding, &headers, is_head) {
+                        Ok(res) => res,
+                        Err(ref why) if why.kind() == io::ErrorKind::NotFound => {
+                            warn!("Not Found {}", &path.display());
+                            not_found_response(&root, &request_path)
                         },
-                        Err(_) => {
-                            error!("read file error: {}", &path.display());
-                            tx.send(Response::new().with_status(StatusCode::InternalServerError))
-                              .expect("Send error on error reading file");
+                        Err(why) => {
+                            error!("read file {} error: {}", &path.display(), why);
+                            Response::new().with_status(StatusCode::InternalServerError)
                         },
                     };
+                    tx.send(res).expect("Send error on file response");
                 });
                 Box::new(rx.map_err(|e| io::Error::new(io::ErrorKind::Other, e).into()))
             }
@@ -70,7 +236,195 @@ impl Service for HttpService {
     }
 }
 
-fn not_found_response() -> Response {
+/// the response of local file `path` for request `headers`
+///
+/// `encoding` is the precompressed variant of `path` to serve. the response is `304 Not Modified`
+/// if the file is not modified since the cached version of client, or `206 Partial Content`
+/// for a single byte range request. html pages are injected with the live reload client script,
+/// other files are streamed in chunks, the body is not read for `HEAD` request.
+fn file_response(path: &Path,
+                 encoding: Option<(Encoding, PathBuf)>,
+                 headers: &Headers,
+                 is_head: bool)
+                 -> io::Result<Response> {
+    let is_html = path.extension().map(|x| x == "html").unwrap_or(false);
+    let mut file = match encoding {
+        Some((_, ref variant)) => File::open(variant)?,
+        None => File::open(path)?,
+    };
+    let metadata = file.metadata()?;
+    let modified = metadata.modified()?;
+    let stamp = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let etag = EntityTag::strong(format!("{:x}-{:x}-{:x}",
+                                         stamp.as_secs(),
+                                         stamp.subsec_nanos(),
+                                         metadata.len()));
+    // http dates are in seconds
+    let last_modified = HttpDate::from(UNIX_EPOCH + Duration::from_secs(stamp.as_secs()));
+    let mime = get_mime_type(path.extension()
+                                 .and_then(|x| x.to_str())
+                                 .unwrap_or(""));
+    let mut res = Response::new()
+        .with_header(ContentType(mime))
+        .with_header(LastModified(last_modified))
+        .with_header(ETag(etag.clone()));
+    if let Some((ref encoding, _)) = encoding {
+        res.headers_mut().set(ContentEncoding(vec![encoding.clone()]));
+        res.headers_mut().set_raw("Vary", "Accept-Encoding");
+    }
+    if is_not_modified(headers, &etag, last_modified) {
+        return Ok(res.with_status(StatusCode::NotModified));
+    }
+
+    if is_html {
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let buf = inject_live_reload(buf);
+        return Ok(res.with_header(ContentLength(buf.len() as u64)).with_body(buf));
+    }
+
+    let len = metadata.len();
+    res.headers_mut().set(AcceptRanges(vec![RangeUnit::Bytes]));
+    let range = match headers.get::<Range>() {
+        Some(&Range::Bytes(ref specs)) if specs.len() == 1 && encoding.is_none() => {
+            match specs[0].to_satisfiable_range(len) {
+                Some(range) => Some(range),
+                None => {
+                    return Ok(res.with_status(StatusCode::RangeNotSatisfiable)
+                                 .with_header(ContentRange(ContentRangeSpec::Bytes {
+                                     range: None,
+                                     instance_length: Some(len),
+                                 }))
+                                 .with_header(ContentLength(0)));
+                },
+            }
+        },
+        _ => None,
+    };
+    let (start, length) = match range {
+        Some((start, end)) => {
+            res.set_status(StatusCode::PartialContent);
+            res.headers_mut().set(ContentRange(ContentRangeSpec::Bytes {
+                range: Some((start, end)),
+                instance_length: Some(len),
+            }));
+            (start, end - start + 1)
+        },
+        None => (0, len),
+    };
+    res.headers_mut().set(ContentLength(length));
+    if is_head {
+        return Ok(res);
+    }
+    file.seek(SeekFrom::Start(start))?;
+    let (tx, body) = Body::pair();
+    thread::spawn(move || stream_file(file, length, tx));
+    Ok(res.with_body(body))
+}
+
+/// send `length` bytes of `file` from its current position to `tx` in chunks
+///
+/// stops when the client is disconnected.
+fn stream_file(file: File, length: u64, mut tx: mpsc::Sender<Result<Chunk, hyper::Error>>) {
+    let mut reader = file.take(length);
+    loop {
+        let mut buf = vec![0; CHUNK_SIZE];
+        let chunk = match reader.read(&mut buf) {
+            Ok(0) => return,
+            Ok(n) => {
+                buf.truncate(n);
+                Ok(Chunk::from(buf))
+            },
+            Err(why) => Err(why.into()),
+        };
+        let is_err = chunk.is_err();
+        tx = match tx.send(chunk).wait() {
+            Ok(tx) if !is_err => tx,
+            _ => return,
+        };
+    }
+}
+
+/// whether the cached version of client is fresh, by `If-None-Match` or `If-Modified-Since`
+///
+/// `If-Modified-Since` is ignored if `If-None-Match` is present.
+fn is_not_modified(headers: &Headers, etag: &EntityTag, last_modified: HttpDate) -> bool {
+    if let Some(if_none_match) = headers.get::<IfNoneMatch>() {
+        return match *if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(ref tags) => tags.iter().any(|x| x.weak_eq(etag)),
+        };
+    }
+    match headers.get::<IfModifiedSince>() {
+        Some(&IfModifiedSince(since)) => last_modified <= since,
+        None => false,
+    }
+}
+
+/// the precompressed variant of `path` in `root_dir` accepted by the client, brotli is preferred
+/// over gzip
+fn compressed_encoding(root_dir: &Path,
+                       path: &Path,
+                       accept: &AcceptEncoding)
+                       -> Option<(Encoding, PathBuf)> {
+    for &(ref encoding, ext) in [(Encoding::Brotli, "br"), (Encoding::Gzip, "gz")].iter() {
+        let accepted = accept.iter().any(|x| &x.item == encoding && x.quality > q(0));
+        let variant = variant_path(path, ext);
+        if accepted && variant.is_file() && is_in_root(root_dir, &variant) {
+            return Some((encoding.clone(), variant));
+        }
+    }
+    None
+}
+
+/// inject the live reload client script into `html` page, before its `</body>` tag
+fn inject_live_reload(mut html: Vec<u8>) -> Vec<u8> {
+    let script = format!("<script>{}</script>", LIVE_RELOAD_JS);
+    let end = html.windows(7)
+                  .rposition(|x| x.eq_ignore_ascii_case(b"</body>"))
+                  .unwrap_or(html.len());
+    html.splice(end..end, script.into_bytes());
+    html
+}
+
+/// the overlay page response of build error `err`
+fn build_error_response(err: &BuildError) -> Response {
+    let html = inject_live_reload(err.render().into_bytes());
+    Response::new()
+        .with_status(StatusCode::InternalServerError)
+        .with_header(ContentLength(html.len() as u64))
+        .with_header(ContentType::html())
+        .with_body(html)
+}
+
+/// escape the html special characters of `text`
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// the not found response of percent-encoded `request_path`
+///
+/// the body is the nearest `404.html` page in the ancestor directories of `request_path`.
+fn not_found_response(root_dir: &Path, request_path: &str) -> Response {
+    let mut dir = request_path.split('?').next().unwrap_or("");
+    while let Some(end) = dir.rfind('/') {
+        dir = &dir[..end];
+        let page = match local_path_for_request(root_dir, &format!("{}/404.html", dir)) {
+            Some(page) => page,
+            None => continue,
+        };
+        if let Ok(html) = fs::read(&page) {
+            let html = inject_live_reload(html);
+            return Response::new()
+                .with_status(StatusCode::NotFound)
+                .with_header(ContentLength(html.len() as u64))
+                .with_header(ContentType::html())
+                .with_body(html);
+        }
+    }
     Response::new()
             .with_status(StatusCode::NotFound)
             .with_header(ContentLength(NOTFOUND.len() as u64))
@@ -78,20 +432,278 @@ fn not_found_response() -> Response {
 }
 
 
+/// the local file path of percent-encoded `request_path` in `root_dir`
+///
+/// directory is resolved to its `index.html`, path without extension falls back to the
+/// `.html` file if not existing, e.g. `/about` to `about.html`.
+/// `None` if the path is invalid, has `..` components, or resolves outside `root_dir`
+/// through symlinks.
 fn local_path_for_request<P: AsRef<Path>>(root_dir: P, request_path: &str) -> Option<PathBuf> {
-    if !request_path.starts_with("/") {
+    let root_dir = root_dir.as_ref();
+    let mut path = join_request_path(root_dir, request_path)?;
+    if path.is_dir() {
+        path.push("index.html");
+    } else if !path.exists() && path.extension().is_none() {
+        let mut html = path.clone().into_os_string();
+        html.push(".html");
+        if Path::new(&html).is_file() {
+            path = PathBuf::from(html);
+        }
+    }
+
+    // the path not existing is served as not found
+    if path.exists() && !is_in_root(root_dir, &path) {
+        return None;
+    }
+    Some(path)
+}
+
+/// `root_dir` joined with the components of percent-encoded `request_path`
+///
+/// `None` if the path is invalid or has `..` components.
+fn join_request_path(root_dir: &Path, request_path: &str) -> Option<PathBuf> {
+    if !request_path.starts_with('/') {
         return None;
     }
     // skip query string
     let end = request_path.find('?').unwrap_or(request_path.len());
-    let request_path = &request_path[0..end];
+    let request_path = match percent_decode(request_path[0..end].as_bytes()).decode_utf8() {
+        Ok(path) => path,
+        Err(_) => return None,
+    };
 
-    let mut path = root_dir.as_ref().to_owned();
+    let mut path = root_dir.to_owned();
     // request path start with "/"
-    path.push(&request_path[1..]);
-    if request_path == "/" {
-        path.push("index.html");
+    for component in Path::new(&request_path[1..]).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {},
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
     }
-
     Some(path)
 }
+
+/// the location of directory `request_path` with the trailing slash, e.g. `/tags` to `/tags/`
+///
+/// `None` if `request_path` is not a directory in `root_dir` or already ends with a slash, so
+/// relative links of the directory index page are resolved in the directory.
+fn directory_redirect(root_dir: &Path, request_path: &str, query: Option<&str>) -> Option<String> {
+    if request_path.ends_with('/') {
+        return None;
+    }
+    let path = join_request_path(root_dir, request_path)?;
+    if !path.is_dir() || !is_in_root(root_dir, &path) {
+        return None;
+    }
+    match query {
+        Some(query) => Some(format!("{}/?{}", request_path, query)),
+        None => Some(format!("{}/", request_path)),
+    }
+}
+
+/// whether the existing `path` is in `root_dir` after resolving symlinks
+fn is_in_root(root_dir: &Path, path: &Path) -> bool {
+    match (path.canonicalize(), root_dir.canonicalize()) {
+        (Ok(real_path), Ok(real_root)) => real_path.starts_with(real_root),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use futures::{Future, Stream};
+    use hyper::StatusCode;
+    use hyper::header::{qitem, AcceptEncoding, ByteRangeSpec, ContentLength, ContentRange,
+                        ContentRangeSpec, Encoding, EntityTag, Headers, HttpDate, IfModifiedSince,
+                        IfNoneMatch, Range};
+    use hyper::server::Response;
+
+    use super::{compressed_encoding, directory_redirect, file_response, is_not_modified,
+                local_path_for_request};
+
+    /// a temporary directory with `root/index.html` and `secret.txt` beside `root`
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mdblog-service-{}-{}", process::id(), name));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(dir.join("root").join("blog").join("tags")).unwrap();
+        fs::write(dir.join("root").join("index.html"), "index").unwrap();
+        fs::write(dir.join("root").join("blog").join("about.html"), "about").unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        dir
+    }
+
+    /// the body of response `res`
+    fn body_of(res: Response) -> Vec<u8> {
+        res.body().concat2().wait().unwrap().to_vec()
+    }
+
+    #[test]
+    fn serves_paths_in_root() {
+        let dir = test_dir("in-root");
+        let root = dir.join("root");
+        assert_eq!(local_path_for_request(&root, "/"), Some(root.join("index.html")));
+        assert_eq!(local_path_for_request(&root, "/index.html?v=1"), Some(root.join("index.html")));
+        assert_eq!(local_path_for_request(&root, "/./blog/a%20b.html"),
+                   Some(root.join("blog").join("a b.html")));
+        assert_eq!(local_path_for_request(&root, "/blog/%E4%BD%A0%E5%A5%BD.html"),
+                   Some(root.join("blog").join("\u{4f60}\u{597d}.html")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn serves_directory_index_and_pretty_urls() {
+        let dir = test_dir("pretty");
+        let root = dir.join("root");
+        assert_eq!(local_path_for_request(&root, "/blog/tags/"),
+                   Some(root.join("blog").join("tags").join("index.html")));
+        assert_eq!(local_path_for_request(&root, "/blog/tags"),
+                   Some(root.join("blog").join("tags").join("index.html")));
+        assert_eq!(local_path_for_request(&root, "/blog/about"),
+                   Some(root.join("blog").join("about.html")));
+        assert_eq!(local_path_for_request(&root, "/blog/missing"),
+                   Some(root.join("blog").join("missing")));
+        assert_eq!(local_path_for_request(&root, "/%2e%2e/secret"), None);
+
+        assert_eq!(directory_redirect(&root, "/blog/tags", None), Some("/blog/tags/".to_string()));
+        assert_eq!(directory_redirect(&root, "/blog", Some("v=1")), Some("/blog/?v=1".to_string()));
+        assert_eq!(directory_redirect(&root, "/blog/tags/", None), None);
+        assert_eq!(directory_redirect(&root, "/", None), None);
+        assert_eq!(directory_redirect(&root, "/blog/about", None), None);
+        assert_eq!(directory_redirect(&root, "/blog/missing", None), None);
+        assert_eq!(directory_redirect(&root, "/blog/%2e%2e/..", None), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_parent_components() {
+        let dir = test_dir("parent");
+        let root = dir.join("root");
+        for path in &["/../secret.txt",
+                      "/blog/../../secret.txt",
+                      "/%2e%2e/secret.txt",
+                      "/%2E%2E/secret.txt",
+                      "/%2e%2e%2fsecret.txt",
+                      "/..%2Fsecret.txt",
+                      "/blog/%2e%2e%2F%2e%2e%2Fsecret.txt",
+                      "/blog/..",
+                      "/%2F..%2Fsecret.txt",
+                      "/%c0%ae%c0%ae/secret.txt",
+                      "index.html"] {
+            assert_eq!(local_path_for_request(&root, path), None, "{}", path);
+        }
+        // double encoded dots are an ordinary file name
+        assert_eq!(local_path_for_request(&root, "/%252e%252e/secret.txt"),
+                   Some(root.join("%2e%2e").join("secret.txt")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_out_of_root() {
+        use std::os::unix::fs::symlink;
+
+        let dir = test_dir("symlink");
+        let root = dir.join("root");
+        symlink(&dir, root.join("outside")).unwrap();
+        symlink(dir.join("secret.txt"), root.join("secret.txt")).unwrap();
+        symlink(root.join("index.html"), root.join("blog").join("home.html")).unwrap();
+        assert_eq!(local_path_for_request(&root, "/outside/secret.txt"), None);
+        assert_eq!(local_path_for_request(&root, "/%6Futside/secret.txt"), None);
+        assert_eq!(local_path_for_request(&root, "/secret.txt"), None);
+        assert_eq!(local_path_for_request(&root, "/blog/home.html"),
+                   Some(root.join("blog").join("home.html")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_compressed_variants_out_of_root() {
+        use std::os::unix::fs::symlink;
+
+        let dir = test_dir("variant");
+        let root = dir.join("root");
+        let accept = AcceptEncoding(vec![qitem(Encoding::Brotli), qitem(Encoding::Gzip)]);
+        fs::write(root.join("main.css"), "css").unwrap();
+        fs::write(root.join("main.css.gz"), "gz").unwrap();
+        symlink(dir.join("secret.txt"), root.join("main.css.br")).unwrap();
+        assert_eq!(compressed_encoding(&root, &root.join("main.css"), &accept),
+                   Some((Encoding::Gzip, root.join("main.css.gz"))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_not_modified() {
+        let etag = EntityTag::strong("1-2-3".to_string());
+        let last_modified = HttpDate::from(UNIX_EPOCH + Duration::from_secs(1000));
+        let mut headers = Headers::new();
+        assert!(!is_not_modified(&headers, &etag, last_modified));
+
+        headers.set(IfModifiedSince(HttpDate::from(UNIX_EPOCH + Duration::from_secs(1000))));
+        assert!(is_not_modified(&headers, &etag, last_modified));
+        headers.set(IfModifiedSince(HttpDate::from(UNIX_EPOCH + Duration::from_secs(999))));
+        assert!(!is_not_modified(&headers, &etag, last_modified));
+
+        headers.set(IfNoneMatch::Items(vec![EntityTag::weak("1-2-3".to_string())]));
+        assert!(is_not_modified(&headers, &etag, last_modified));
+        headers.set(IfNoneMatch::Any);
+        assert!(is_not_modified(&headers, &etag, last_modified));
+        // `If-Modified-Since` is ignored if `If-None-Match` is present
+        headers.set(IfModifiedSince(HttpDate::from(UNIX_EPOCH + Duration::from_secs(1000))));
+        headers.set(IfNoneMatch::Items(vec![EntityTag::strong("4-5-6".to_string())]));
+        assert!(!is_not_modified(&headers, &etag, last_modified));
+    }
+
+    #[test]
+    fn serves_byte_ranges() {
+        let dir = test_dir("range");
+        let path = dir.join("root").join("clip.mp4");
+        fs::write(&path, "0123456789").unwrap();
+
+        let res = file_response(&path, None, &Headers::new(), false).unwrap();
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(body_of(res), b"0123456789");
+
+        let mut headers = Headers::new();
+        headers.set(Range::bytes(2, 5));
+        let res = file_response(&path, None, &headers, false).unwrap();
+        assert_eq!(res.status(), StatusCode::PartialContent);
+        assert_eq!(res.headers().get(),
+                   Some(&ContentRange(ContentRangeSpec::Bytes {
+                       range: Some((2, 5)),
+                       instance_length: Some(10),
+                   })));
+        assert_eq!(res.headers().get(), Some(&ContentLength(4)));
+        assert_eq!(body_of(res), b"2345");
+
+        headers.set(Range::Bytes(vec![ByteRangeSpec::Last(3)]));
+        let res = file_response(&path, None, &headers, false).unwrap();
+        assert_eq!(res.status(), StatusCode::PartialContent);
+        assert_eq!(body_of(res), b"789");
+
+        // multiple ranges are served as the whole file
+        headers.set(Range::bytes_multi(vec![(0, 1), (4, 5)]));
+        let res = file_response(&path, None, &headers, false).unwrap();
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(body_of(res), b"0123456789");
+
+        headers.set(Range::Bytes(vec![ByteRangeSpec::AllFrom(10)]));
+        let res = file_response(&path, None, &headers, false).unwrap();
+        assert_eq!(res.status(), StatusCode::RangeNotSatisfiable);
+        assert_eq!(res.headers().get(),
+                   Some(&ContentRange(ContentRangeSpec::Bytes {
+                       range: None,
+                       instance_length: Some(10),
+                   })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+(function () {
+  if (!window.EventSource) {
+    return;
+  }
+  var source = new EventSource("/__mdblog/events");
+  source.addEventListener("reload", function () {
+    window.location.reload();
+  });
+  source.addEventListener("css", function () {
+    var xhr = new XMLHttpRequest();
+    xhr.onload = function () {
+      var doc = new DOMParser().parseFromString(xhr.responseText, "text/html");
+      var links = document.querySelectorAll('link[rel="stylesheet"]');
+      var fresh = doc.querySelectorAll('link[rel="stylesheet"]');
+      if (xhr.status !== 200 || links.length !== fresh.length) {
+        window.location.reload();
+        return;
+      }
+      for (var i = 0; i < links.length; i++) {
+        var href = fresh[i].getAttribute("href");
+        if (href === links[i].getAttribute("href")) {
+          href = href.split("?")[0] + "?t=" + Date.now();
+        }
+        links[i].setAttribute("href", href);
+      }
+    };
+    xhr.onerror = function () {
+      window.location.reload();
+    };
+    xhr.open("GET", window.location.href);
+    xhr.send();
+  });
+})();
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use config::{Source, Value, ConfigError};
+use toml::Value as TomlValue;
 
 /// blog setting
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,10 +15,37 @@ pub struct Settings {
     pub site_motto: String,
     /// blog footer note
     pub footer_note: String,
+    /// base url of the published site, e.g. `https://example.com`, for absolute links of feeds
+    pub site_url: String,
+    /// blog language code, e.g. `en`, `zh`
+    pub language: String,
+    /// languages of multilingual blog, e.g. `["en", "zh"]`, empty for single language blog
+    ///
+    /// the pages of every language are generated into its language directory, e.g. `/zh/`.
+    pub languages: Vec<String>,
     /// blog build directory
     pub build_dir: String,
     /// blog rebuild interval
     pub rebuild_interval: i64,
+    /// minify html, css and javascript files of build output
+    pub minify: bool,
+    /// write gzip and brotli compressed variants of build output
+    pub precompress: bool,
+    /// glob patterns of paths in build directory never removed by build or clean
+    pub preserved_paths: Vec<String>,
+    /// default templates of posts in directories
+    pub post_templates: Vec<PostTemplate>,
+    /// options of blog theme, declared by the theme manifest
+    pub theme_options: BTreeMap<String, TomlValue>,
+}
+
+/// default template of posts in a directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostTemplate {
+    /// directory relative to `posts` directory
+    pub dir: String,
+    /// template name, e.g. `slides.tpl`
+    pub template: String,
 }
 
 impl Default for Settings {
@@ -28,8 +56,16 @@ impl Default for Settings {
             site_logo: String::from("/static/logo.png"),
             site_motto: String::from("Simple is Beautiful!"),
             footer_note: String::from("Keep It Simple, Stupid!"),
+            site_url: String::new(),
+            language: String::from("en"),
+            languages: Vec::new(),
             build_dir: String::from("_build"),
             rebuild_interval: 2,
+            minify: false,
+            precompress: false,
+            preserved_paths: vec![String::from("CNAME"), String::from(".git")],
+            post_templates: Vec::new(),
+            theme_options: BTreeMap::new(),
         }
     }
 }
@@ -46,8 +82,49 @@ impl Source for Settings {
         map.insert("site_logo".to_string(), self.site_logo.clone().into());
         map.insert("site_motto".to_string(), self.site_motto.clone().into());
         map.insert("footer_note".to_string(), self.footer_note.clone().into());
+        map.insert("site_url".to_string(), self.site_url.clone().into());
+        map.insert("language".to_string(), self.language.clone().into());
+        map.insert("languages".to_string(), self.languages.clone().into());
         map.insert("build_dir".to_string(), self.build_dir.clone().into());
         map.insert("rebuild_interval".to_string(), self.rebuild_interval.clone().into());
+        map.insert("minify".to_string(), self.minify.into());
+        map.insert("precompress".to_string(), self.precompress.into());
+        map.insert("preserved_paths".to_string(), self.preserved_paths.clone().into());
+        let post_templates: Vec<Value> = self.post_templates
+                                             .iter()
+                                             .map(|x| {
+                                                 let mut item = HashMap::new();
+                                                 let dir = Value::from(x.dir.clone());
+                                                 let template = Value::from(x.template.clone());
+                                                 item.insert("dir".to_string(), dir);
+                                                 item.insert("template".to_string(), template);
+                                                 item.into()
+                                             })
+                                             .collect();
+        map.insert("post_templates".to_string(), post_templates.into());
+        let theme_options: HashMap<String, Value> = self.theme_options
+                                                        .iter()
+                                                        .map(|(k, v)| (k.clone(), config_value(v)))
+                                                        .collect();
+        map.insert("theme_options".to_string(), theme_options.into());
         Ok(map)
     }
 }
+
+/// convert toml value into config value
+fn config_value(value: &TomlValue) -> Value {
+    match *value {
+        TomlValue::String(ref s) => s.clone().into(),
+        TomlValue::Integer(i) => i.into(),
+        TomlValue::Float(f) => f.into(),
+        TomlValue::Boolean(b) => b.into(),
+        TomlValue::Datetime(ref d) => d.to_string().into(),
+        TomlValue::Array(ref a) => a.iter().map(config_value).collect::<Vec<Value>>().into(),
+        TomlValue::Table(ref t) => {
+            t.iter()
+             .map(|(k, v)| (k.clone(), config_value(v)))
+             .collect::<HashMap<String, Value>>()
+             .into()
+        },
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use chrono::format::{Item, StrftimeItems};
+use serde_json::{Map, Value};
+use tera::{GlobalFn, Result as TeraResult, Template};
+use tera::ast::{Expr, ExprVal, Node};
+
+use i18n::I18n;
+use post::{render_markdown, tag_url, Post};
+
+/// datetime format of template values, e.g. `datetime` of post and `now()`
+pub static DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// whether `fmt` is a valid strftime datetime format
+pub fn is_valid_date_format(fmt: &str) -> bool {
+    StrftimeItems::new(fmt).all(|item| !matches!(item, Item::Error))
+}
+
+/// string argument `name` of template function or filter `func`
+fn str_arg<'a>(func: &str, args: &'a HashMap<String, Value>, name: &str) -> TeraResult<&'a str> {
+    match args.get(name).and_then(|x| x.as_str()) {
+        Some(value) => Ok(value),
+        None => Err(format!("`{}` requires a string `{}` argument", func, name).into()),
+    }
+}
+
+/// post context of template functions, with its content and tags
+fn post_value(post: &Post, i18n: &I18n) -> Value {
+    let mut map = post.map(i18n);
+    map.insert("path".to_string(), Value::String(post.path.to_string_lossy().into_owned()));
+    map.insert("content".to_string(), Value::String(post.content()));
+    let tags = post.tags()
+                   .iter()
+                   .map(|name| {
+                       let mut tag = Map::new();
+                       tag.insert("name".to_string(), Value::String(name.to_string()));
+                       tag.insert("url".to_string(), Value::String(tag_url(post.lang(), name)));
+                       Value::Object(tag)
+                   })
+                   .collect();
+    map.insert("tags".to_string(), Value::Array(tags));
+    Value::Object(map)
+}
+
+/// template global function `get_post(path="hello.md")`
+///
+/// `path` is relative to `posts` directory, return the post with its `content` and `tags`.
+pub fn make_get_post_fn(posts: BTreeMap<String, Arc<Post>>, i18n: I18n) -> GlobalFn {
+    Box::new(move |args| -> TeraResult<Value> {
+        let path = str_arg("get_post", &args, "path")?;
+        let path = path.trim_start_matches("posts/").trim_start_matches('/');
+        match posts.get(path) {
+            Some(post) => Ok(post_value(post, &i18n)),
+            None => Err(format!("global function `get_post`: post {} not found", path).into()),
+        }
+    })
+}
+
+/// template global function `get_tag(name="hello")`, return the tag with its `posts`
+///
+/// `tags` are the tagged posts of language `lang` on multilingual site.
+pub fn make_get_tag_fn(tags: BTreeMap<String, Vec<Arc<Post>>>,
+                       lang: Option<String>,
+                       i18n: I18n)
+                       -> GlobalFn {
+    Box::new(move |args| -> TeraResult<Value> {
+        let name = str_arg("get_tag", &args, "name")?;
+        let posts = match tags.get(name) {
+            Some(posts) => posts,
+            None => return Err(format!("global function `get_tag`: tag {} not found", name).into()),
+        };
+        let mut map = Map::new();
+        map.insert("name".to_string(), Value::String(name.to_string()));
+        map.insert("url".to_string(), Value::String(tag_url(lang.as_deref(), name)));
+        map.insert("num".to_string(), Value::String(posts.len().to_string()));
+        let posts = posts.iter().map(|x| Value::Object(x.map(&i18n))).collect();
+        map.insert("posts".to_string(), Value::Array(posts));
+        Ok(Value::Object(map))
+    })
+}
+
+/// template global function `url_for(path="posts/hello.md")`
+///
+/// markdown post path is resolved to the post url, other paths to the absolute url.
+/// `posts` are keyed by path relative to `posts` directory, unknown post is an error.
+pub fn make_url_for_fn(posts: BTreeMap<String, Arc<Post>>) -> GlobalFn {
+    Box::new(move |args| -> TeraResult<Value> {
+        let path = str_arg("url_for", &args, "path")?.trim_start_matches('/');
+        if path.ends_with(".md") {
+            let path = path.trim_start_matches("posts/");
+            return match posts.get(path) {
+                Some(post) => Ok(Value::String(post.url().to_string_lossy().replace('\\', "/"))),
+                None => Err(format!("global function `url_for`: post {} not found", path).into()),
+            };
+        }
+        Ok(Value::String(format!("/{}", path)))
+    })
+}
+
+/// template global function `now()`, return the current local datetime
+pub fn make_now_fn() -> GlobalFn {
+    Box::new(|_| -> TeraResult<Value> {
+        Ok(Value::String(Local::now().format(DATETIME_FORMAT).to_string()))
+    })
+}
+
+/// template filter `date_format(fmt="%Y/%m/%d")`
+///
+/// the input is a `%Y-%m-%d %H:%M:%S`, `%Y-%m-%d` or rfc3339 datetime string, or a timestamp.
+pub fn date_format(value: Value, args: HashMap<String, Value>) -> TeraResult<Value> {
+    let fmt = str_arg("date_format", &args, "fmt")?;
+    if !is_valid_date_format(fmt) {
+        return Err(format!("filter `date_format`: invalid format {}", fmt).into());
+    }
+    let datetime = match value {
+        Value::Number(ref n) if n.is_i64() => {
+            match Local.timestamp_opt(n.as_i64().unwrap(), 0).single() {
+                Some(datetime) => datetime.naive_local(),
+                None => return Err(format!("filter `date_format`: invalid timestamp {}", n).into()),
+            }
+        },
+        Value::String(ref s) => {
+            if let Ok(datetime) = NaiveDateTime::parse_from_str(s, DATETIME_FORMAT) {
+                datetime
+            } else if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+                date.and_hms(0, 0, 0)
+            } else if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+                datetime.naive_local()
+            } else {
+                return Err(format!("filter `date_format`: invalid datetime {}", s).into());
+            }
+        },
+        _ => return Err(format!("filter `date_format`: invalid datetime {}", value).into()),
+    };
+    Ok(Value::String(datetime.format(fmt).to_string()))
+}
+
+/// template filter `reading_time`, return the reading minutes of markdown or html text
+///
+/// counts 200 words per minute, every CJK character is counted as a word.
+pub fn reading_time(value: Value, _: HashMap<String, Value>) -> TeraResult<Value> {
+    let text = match value.as_str() {
+        Some(text) => text,
+        None => return Err("filter `reading_time` requires a string".into()),
+    };
+    let mut words = 0;
+    let mut in_word = false;
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if in_tag => {},
+            '\u{2e80}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' | '\u{ac00}'..='\u{d7af}' => {
+                words += 1;
+                in_word = false;
+                continue;
+            },
+            _ if c.is_alphanumeric() => {
+                if !in_word {
+                    words += 1;
+                }
+                in_word = true;
+                continue;
+            },
+            _ => {},
+        }
+        in_word = false;
+    }
+    Ok(Value::from((words + 199) / 200))
+}
+
+/// template filter `markdown`, render markdown text into html
+pub fn markdown(value: Value, _: HashMap<String, Value>) -> TeraResult<Value> {
+    match value.as_str() {
+        Some(text) => Ok(Value::String(render_markdown(text))),
+        None => Err("filter `markdown` requires a string".into()),
+    }
+}
+
+/// whether `template` calls any global function of `names`
+pub fn calls_function(template: &Template, names: &[&str]) -> bool {
+    nodes_call(&template.ast, names)
+    || template.macros.values().any(|x| nodes_call(&x.body, names))
+}
+
+/// whether any of `nodes` calls a global function of `names`
+fn nodes_call(nodes: &[Node], names: &[&str]) -> bool {
+    nodes.iter().any(|node| {
+        match *node {
+            Node::VariableBlock(ref expr) => expr_calls(expr, names),
+            Node::MacroDefinition(_, ref def, _) => {
+                def.args.values().any(|x| x.as_ref().is_some_and(|x| expr_calls(x, names)))
+                || nodes_call(&def.body, names)
+            },
+            Node::Set(_, ref set) => expr_calls(&set.value, names),
+            Node::FilterSection(_, ref section, _) => {
+                section.filter.args.values().any(|x| expr_calls(x, names))
+                || nodes_call(&section.body, names)
+            },
+            Node::Block(_, ref block, _) => nodes_call(&block.body, names),
+            Node::Forloop(_, ref forloop, _) => {
+                expr_calls(&forloop.container, names) || nodes_call(&forloop.body, names)
+            },
+            Node::If(ref branches, _) => {
+                branches.conditions
+                        .iter()
+                        .any(|(_, cond, body)| expr_calls(cond, names) || nodes_call(body, names))
+                || branches.otherwise.as_ref().is_some_and(|x| nodes_call(&x.1, names))
+            },
+            _ => false,
+        }
+    })
+}
+
+/// whether `expr` calls a global function of `names`
+fn expr_calls(expr: &Expr, names: &[&str]) -> bool {
+    let filters_call = expr.filters.iter().any(|x| x.args.values().any(|x| expr_calls(x, names)));
+    filters_call || match expr.val {
+        ExprVal::FunctionCall(ref call) => {
+            names.contains(&call.name.as_str()) || call.args.values().any(|x| expr_calls(x, names))
+        },
+        ExprVal::MacroCall(ref call) => call.args.values().any(|x| expr_calls(x, names)),
+        ExprVal::Math(ref math) => expr_calls(&math.lhs, names) || expr_calls(&math.rhs, names),
+        ExprVal::Logic(ref logic) => {
+            expr_calls(&logic.lhs, names) || expr_calls(&logic.rhs, names)
+        },
+        ExprVal::Test(ref test) => test.args.iter().any(|x| expr_calls(x, names)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// arguments of template function or filter
+    fn args(pairs: &[(&str, &str)]) -> HashMap<String, Value> {
+        pairs.iter().map(|&(k, v)| (k.to_string(), Value::String(v.to_string()))).collect()
+    }
+
+    #[test]
+    fn date_format_accepts_datetime_date_and_rfc3339() {
+        let fmt = args(&[("fmt", "%Y/%m/%d %H:%M")]);
+        for &(input, output) in &[("2018-03-04 05:06:07", "2018/03/04 05:06"),
+                                  ("2018-03-04", "2018/03/04 00:00"),
+                                  ("2018-03-04T05:06:07+08:00", "2018/03/04 05:06")] {
+            let res = date_format(Value::String(input.to_string()), fmt.clone()).unwrap();
+            assert_eq!(res, Value::String(output.to_string()));
+        }
+        assert!(date_format(Value::from(0), fmt.clone()).is_ok());
+        assert!(date_format(Value::String("yesterday".to_string()), fmt.clone()).is_err());
+        assert!(date_format(Value::Bool(true), fmt).is_err());
+        assert!(date_format(Value::from(0), args(&[("fmt", "%Q")])).is_err());
+        assert!(date_format(Value::from(0), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn reading_time_counts_words_and_cjk_characters() {
+        let time = |text: &str| reading_time(Value::String(text.to_string()), HashMap::new());
+        assert_eq!(time("").unwrap(), Value::from(0));
+        assert_eq!(time("hello, world").unwrap(), Value::from(1));
+        assert_eq!(time(&"word ".repeat(200)).unwrap(), Value::from(1));
+        assert_eq!(time(&"word ".repeat(201)).unwrap(), Value::from(2));
+        assert_eq!(time(&"<a href=\"x y z\">word</a> ".repeat(200)).unwrap(), Value::from(1));
+        assert_eq!(time(&"字".repeat(400)).unwrap(), Value::from(2));
+        assert!(reading_time(Value::from(1), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn calls_function_finds_nested_calls() {
+        let names = ["get_post", "url_for"];
+        let calls = |content: &str| {
+            calls_function(&Template::new("test.tpl", None, content).unwrap(), &names)
+        };
+        assert!(!calls("{{ now() }} {{ asset_url(path=\"main.css\") }}"));
+        assert!(calls("{{ url_for(path=\"posts/a.md\") }}"));
+        assert!(calls("{% block main %}{% if x %}{{ get_post(path=\"a.md\") }}\
+                       {% endif %}{% endblock main %}"));
+        assert!(calls("{% for x in posts %}{{ x | default(value=url_for(path=\"a\")) }}\
+                       {% endfor %}"));
+        assert!(calls("{% set post = get_post(path=\"a.md\") %}"));
+        assert!(calls("{% macro link() %}{{ url_for(path=\"a\") }}{% endmacro link %}"));
+    }
+}
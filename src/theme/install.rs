@@ -0,0 +1,197 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use flate2::read::GzDecoder;
+use tar::Archive;
+use zip::ZipArchive;
+
+use errors::{Error, Result};
+use super::ThemeManifest;
+
+/// install a theme into `root/_themes` from `source`, return the installed theme name
+///
+/// `source` can be a `.tar.gz`/`.tgz`/`.zip` archive file, or a git repository path or url.
+/// the theme can be at the top of the source, or in its only top-level directory.
+/// the theme name is the `name` of its `theme.toml`, or the source file name.
+pub fn install_theme(root: &Path, source: &str) -> Result<String> {
+    let theme_root = root.join("_themes");
+    let staging = theme_root.join(".install");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&theme_root)?;
+    let res = unpack_source(source, &staging).and_then(|_| {
+        let src_dir = find_theme_dir(&staging)?;
+        let manifest = ThemeManifest::read(&src_dir, source)?;
+        let name = match manifest.name {
+            Some(ref name) => name.clone(),
+            None => source_name(source),
+        };
+        if name.is_empty() || name.starts_with('.') || name.contains('/') || name.contains('\\') {
+            return Err(Error::ThemeInvalid(source.to_string(), format!("invalid name `{}`", name)));
+        }
+        manifest.check_version(&name)?;
+        let dest_dir = theme_root.join(&name);
+        if dest_dir.exists() {
+            return Err(Error::ThemeExisted(name));
+        }
+        fs::rename(&src_dir, &dest_dir)?;
+        Ok(name)
+    });
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    res
+}
+
+/// update the git-sourced theme `root/_themes/name` from its origin repository
+///
+/// the updated theme is checked by its manifest and `validate`, and reset to the old revision
+/// if any check fails.
+pub fn update_theme<F>(root: &Path, name: &str, validate: F) -> Result<()>
+    where F: FnOnce() -> Result<()>
+{
+    let theme_dir = root.join("_themes").join(name);
+    if !theme_dir.is_dir() {
+        return Err(Error::ThemeNotFound(name.to_string()));
+    }
+    if !theme_dir.join(".git").exists() {
+        return Err(Error::ThemeInvalid(name.to_string(),
+                                       "not installed from a git repository".to_string()));
+    }
+    let head = git(&["rev-parse", "HEAD"], Some(&theme_dir))?;
+    git(&["pull", "--ff-only"], Some(&theme_dir))?;
+    let res = ThemeManifest::read(&theme_dir, name).and_then(|x| x.check_version(name))
+                                                   .and_then(|_| validate());
+    if res.is_err() {
+        warn!("theme({}) update rejected, reset to {}", name, head);
+        git(&["reset", "--hard", &head], Some(&theme_dir))?;
+    }
+    res
+}
+
+/// unpack archive file or clone git repository `source` into `dest`
+fn unpack_source(source: &str, dest: &Path) -> Result<()> {
+    let path = Path::new(source);
+    if path.is_file() {
+        if source.ends_with(".tar.gz") || source.ends_with(".tgz") {
+            debug!("unpacking tar archive {} ...", source);
+            Archive::new(GzDecoder::new(File::open(path)?)).unpack(dest)?;
+        } else if source.ends_with(".zip") {
+            debug!("unpacking zip archive {} ...", source);
+            ZipArchive::new(File::open(path)?)
+                .and_then(|mut archive| archive.extract(dest))
+                .map_err(|why| Error::ThemeInvalid(source.to_string(), why.to_string()))?;
+        } else {
+            return Err(Error::Argument(format!("unsupported theme archive: {}", source)));
+        }
+        return Ok(());
+    }
+    if path.is_dir() && !path.join(".git").exists() {
+        return Err(Error::Argument(format!("theme directory {} is not a git repository", source)));
+    }
+    debug!("cloning git repository {} ...", source);
+    git(&["clone", "--", source, &dest.to_string_lossy()], None)?;
+    Ok(())
+}
+
+/// the theme directory in unpacked source `dir`
+fn find_theme_dir(dir: &Path) -> Result<PathBuf> {
+    if is_theme_dir(dir) {
+        return Ok(dir.to_owned());
+    }
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.file_name().map(|x| x.to_string_lossy().starts_with('.')).unwrap_or(true) {
+            entries.push(path);
+        }
+    }
+    if entries.len() == 1 && is_theme_dir(&entries[0]) {
+        return Ok(entries.remove(0));
+    }
+    Err(Error::ThemeInvalid(dir.display().to_string(),
+                            "no `theme.toml`, `static` or `templates` found".to_string()))
+}
+
+/// whether `dir` looks like a theme directory
+fn is_theme_dir(dir: &Path) -> bool {
+    dir.join("theme.toml").is_file() || dir.join("templates").is_dir()
+    || dir.join("static").is_dir()
+}
+
+/// theme name derived from the source file name, e.g. `foo.tar.gz` -> `foo`
+fn source_name(source: &str) -> String {
+    let name = source.trim_end_matches(['/', '\\'])
+                     .rsplit(['/', '\\', ':'])
+                     .next()
+                     .unwrap_or("");
+    [".tar.gz", ".tgz", ".zip", ".git"].iter()
+                                          .fold(name, |name, ext| name.trim_end_matches(ext))
+                                          .to_string()
+}
+
+/// run git command with `args` in directory `dir`, return its trimmed stdout
+fn git(args: &[&str], dir: Option<&Path>) -> Result<String> {
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let output = cmd.args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(Error::Command(format!("git {}", args.join(" ")), stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    /// commit `theme.toml` of `content` into git repository `repo`
+    fn commit_manifest(repo: &Path, content: &str) {
+        fs::write(repo.join("theme.toml"), content).unwrap();
+        git(&["add", "theme.toml"], Some(repo)).unwrap();
+        git(&["-c", "user.name=test", "-c", "user.email=test@localhost", "commit", "-m", "theme"],
+            Some(repo)).unwrap();
+    }
+
+    #[test]
+    fn update_resets_rejected_theme() {
+        let root = env::temp_dir().join(format!("mdblog-install-{}", process::id()));
+        let repo = root.join("repo");
+        fs::create_dir_all(&repo).unwrap();
+        git(&["init", "-q"], Some(&repo)).unwrap();
+        commit_manifest(&repo, "name = \"foo\"\n");
+        let blog = root.join("blog");
+        assert_eq!(install_theme(&blog, &repo.to_string_lossy()).unwrap(), "foo");
+        let theme_dir = blog.join("_themes/foo");
+        let head = git(&["rev-parse", "HEAD"], Some(&theme_dir)).unwrap();
+
+        commit_manifest(&repo, "name = \"foo\"\nmin_mdblog_version = \"99.0.0\"\n");
+        assert!(update_theme(&blog, "foo", || Ok(())).is_err());
+        assert_eq!(git(&["rev-parse", "HEAD"], Some(&theme_dir)).unwrap(), head);
+
+        commit_manifest(&repo, "name = \"foo\"\nversion = \"2.0.0\"\n");
+        let reject = || Err(Error::Argument("broken template".to_string()));
+        assert!(update_theme(&blog, "foo", reject).is_err());
+        assert_eq!(git(&["rev-parse", "HEAD"], Some(&theme_dir)).unwrap(), head);
+
+        update_theme(&blog, "foo", || Ok(())).unwrap();
+        assert_ne!(git(&["rev-parse", "HEAD"], Some(&theme_dir)).unwrap(), head);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn source_name_strips_archive_extensions() {
+        assert_eq!(source_name("/tmp/foo.tar.gz"), "foo");
+        assert_eq!(source_name("https://example.com/user/bar.git"), "bar");
+        assert_eq!(source_name("git@example.com:baz/"), "baz");
+        assert_eq!(source_name("C:\\themes\\qux.zip"), "qux");
+    }
+}
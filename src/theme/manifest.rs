@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use semver::Version;
+use toml::{self, Value};
+
+use errors::{Error, Result};
+
+/// theme manifest, the `theme.toml` file of theme directory
+///
+/// ```toml
+/// name = "simple"
+/// version = "0.1.0"
+/// author = "FuGangqiang"
+/// description = "the default theme"
+/// min_mdblog_version = "0.6.0"
+/// extends = "simple"
+///
+/// [options.accent_color]
+/// type = "string"
+/// default = "#0366d6"
+/// description = "color of links"
+/// ```
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ThemeManifest {
+    /// theme name
+    pub name: Option<String>,
+    /// theme version
+    pub version: Option<String>,
+    /// theme author
+    pub author: Option<String>,
+    /// theme description
+    pub description: Option<String>,
+    /// the minimum mdblog version required by theme
+    pub min_mdblog_version: Option<String>,
+    /// the built-in theme the theme is created from
+    pub origin: Option<String>,
+    /// parent theme name
+    pub extends: Option<String>,
+    /// declared options configured by blog `[theme_options]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, ThemeOption>,
+}
+
+/// theme option declaration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThemeOption {
+    /// option value type
+    #[serde(rename = "type")]
+    pub kind: OptionKind,
+    /// option default value
+    pub default: Option<Value>,
+    /// option description
+    pub description: Option<String>,
+}
+
+/// theme option value type
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionKind {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Array,
+}
+
+impl OptionKind {
+    /// whether `value` is of this type
+    pub fn matches(&self, value: &Value) -> bool {
+        matches!((*self, value),
+                 (OptionKind::String, &Value::String(_))
+                 | (OptionKind::Integer, &Value::Integer(_))
+                 | (OptionKind::Float, &Value::Float(_))
+                 | (OptionKind::Float, &Value::Integer(_))
+                 | (OptionKind::Boolean, &Value::Boolean(_))
+                 | (OptionKind::Array, &Value::Array(_)))
+    }
+}
+
+impl ThemeManifest {
+    /// read the manifest of theme directory `dir`, default manifest if `theme.toml` not existed
+    ///
+    /// the parse error is reported as invalid theme `source`, e.g. the theme name or the
+    /// install source.
+    pub fn read(dir: &Path, source: &str) -> Result<ThemeManifest> {
+        let path = dir.join("theme.toml");
+        if !path.exists() {
+            return Ok(ThemeManifest::default());
+        }
+        let mut content = String::new();
+        File::open(&path)?.read_to_string(&mut content)?;
+        toml::from_str(&content).map_err(|why| {
+            Error::ThemeInvalid(source.to_string(), format!("theme.toml: {}", why))
+        })
+    }
+
+    /// check the manifest of theme `name` is supported by current mdblog version
+    pub fn check_version(&self, name: &str) -> Result<()> {
+        let min_version = match self.min_mdblog_version {
+            Some(ref v) => v,
+            None => return Ok(()),
+        };
+        let invalid = |reason: String| Error::ThemeInvalid(name.to_string(), reason);
+        let required = Version::parse(min_version).map_err(|e| invalid(e.to_string()))?;
+        let current = Version::parse(env!("CARGO_PKG_VERSION")).expect("mdblog version error");
+        if current < required {
+            return Err(invalid(format!("requires mdblog {}, current version is {}",
+                                       required, current)));
+        }
+        Ok(())
+    }
+
+    /// validate blog `[theme_options]` against the declared options of theme `name`
+    ///
+    /// return all declared options, with the default value for options not configured.
+    pub fn resolve_options(&self,
+                           name: &str,
+                           site_options: &BTreeMap<String, Value>)
+                           -> Result<BTreeMap<String, Value>> {
+        let mut options = BTreeMap::new();
+        for (key, value) in site_options {
+            let declared = self.options.get(key).ok_or_else(|| {
+                Error::ThemeInvalid(name.to_string(), format!("unknown theme option `{}`", key))
+            })?;
+            if !declared.kind.matches(value) {
+                return Err(Error::ThemeInvalid(name.to_string(),
+                                               format!("theme option `{}` must be {:?}",
+                                                       key,
+                                                       declared.kind)));
+            }
+            options.insert(key.clone(), value.clone());
+        }
+        for (key, declared) in &self.options {
+            if options.contains_key(key) {
+                continue;
+            }
+            if let Some(ref default) = declared.default {
+                options.insert(key.clone(), default.clone());
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r##"
+[options.accent_color]
+type = "string"
+default = "#3366ff"
+
+[options.columns]
+type = "integer"
+
+[options.ratio]
+type = "float"
+default = 1.5
+
+[options.dark]
+type = "boolean"
+default = false
+
+[options.links]
+type = "array"
+"##;
+
+    /// blog `[theme_options]` of `content`
+    fn site_options(content: &str) -> BTreeMap<String, Value> {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn resolve_options_fills_defaults() {
+        let manifest: ThemeManifest = toml::from_str(MANIFEST).unwrap();
+        let options = manifest.resolve_options("foo", &site_options("columns = 2\nratio = 2"))
+                              .unwrap();
+        assert_eq!(options["accent_color"], Value::String("#3366ff".to_string()));
+        assert_eq!(options["columns"], Value::Integer(2));
+        assert_eq!(options["ratio"], Value::Integer(2));
+        assert_eq!(options["dark"], Value::Boolean(false));
+        assert!(!options.contains_key("links"));
+    }
+
+    #[test]
+    fn resolve_options_checks_types() {
+        let manifest: ThemeManifest = toml::from_str(MANIFEST).unwrap();
+        for valid in &["accent_color = \"red\"", "columns = 3", "ratio = 0.5", "dark = true",
+                       "links = [\"a\", \"b\"]"] {
+            assert!(manifest.resolve_options("foo", &site_options(valid)).is_ok(), "{}", valid);
+        }
+        for invalid in &["accent_color = 1", "columns = 1.5", "ratio = \"1\"", "dark = \"yes\"",
+                         "links = \"a\"", "unknown = 1"] {
+            match manifest.resolve_options("foo", &site_options(invalid)) {
+                Err(Error::ThemeInvalid(name, _)) => assert_eq!(name, "foo"),
+                other => panic!("{}: unexpected result: {:?}", invalid, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+<!doctype html>
+<html lang="{{ language }}">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <link rel="icon" href="{{ asset_url(path="favicon.png") }}">
+  <link rel="stylesheet" href="{{ asset_url(path="main.css") }}">
+  <link rel="alternate" type="application/atom+xml" title="{{ site_name }}" href="{{ feed_url }}">
+  {% for translation in translations %}
+  <link rel="alternate" hreflang="{{ translation.lang }}" href="{{ translation.url }}">
+  {% endfor %}
+  {% block css %}{% endblock css %}
+  <title>{{ title }}</title>
+</head>
+<body>
+<header>
+  <h1 id="site-name"><a href="{{ home_url }}" title="{{ site_name }}">{{ site_name }}</a></h1>
+  <p id="site-motto">{{ site_motto }}</p>
+</header>
+
+<main>
+  {% block main %}{% endblock main %}
+</main>
+
+<nav class="tags">
+  {% for tag in all_tags %}
+    <a href="{{ tag.url }}">{{ tag.name }}<sup>{{ tag.num }}</sup></a>
+  {% endfor %}
+</nav>
+
+<footer>
+  <p>{{ footer_note }}</p>
+</footer>
+{% block js %}{% endblock js %}
+</body>
+</html>
@@ -1,30 +1,120 @@
+mod install;
+mod manifest;
+mod upgrade;
+
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str;
+use std::sync::Mutex;
+use grass::{self, Fs};
+use serde_json::Value as TeraValue;
+use tera::{GlobalFn, Result as TeraResult};
+use toml::{self, Value as TomlValue};
+use toml::value::Table;
+use walkdir::WalkDir;
+use cache::hash_of;
+use minify::{minify_css, minify_js};
 use utils::create_file;
 use errors::{Error, Result};
+use i18n::{I18n, DEFAULT_LANGUAGE};
+use template::is_valid_date_format;
+pub use self::install::{install_theme, update_theme};
+pub use self::manifest::ThemeManifest;
+pub use self::upgrade::{diff_theme, upgrade_theme};
+
+/// theme embedded in mdblog
+struct BuiltinTheme {
+    name: &'static str,
+    manifest: &'static str,
+    /// static files, keyed by path relative to `static` directory
+    statics: &'static [(&'static str, &'static [u8])],
+    /// template files, keyed by path relative to `templates` directory
+    templates: &'static [(&'static str, &'static [u8])],
+    /// translation files, keyed by path relative to `i18n` directory
+    i18n: &'static [(&'static str, &'static [u8])],
+}
+
+/// built-in themes, selectable by `theme` of blog settings without `_themes` directory
+static BUILTIN_THEMES: &'static [BuiltinTheme] = &[
+    BuiltinTheme {
+        name: "simple",
+        manifest: include_str!("simple/theme.toml"),
+        statics: &[
+            ("favicon.png", include_bytes!("simple/static/favicon.png")),
+            ("logo.png", include_bytes!("simple/static/logo.png")),
+            ("main.css", include_bytes!("simple/static/main.css")),
+            ("main.js", include_bytes!("simple/static/main.js")),
+        ],
+        templates: &[
+            ("base.tpl", include_bytes!("simple/templates/base.tpl")),
+            ("index.tpl", include_bytes!("simple/templates/index.tpl")),
+            ("post.tpl", include_bytes!("simple/templates/post.tpl")),
+            ("tag.tpl", include_bytes!("simple/templates/tag.tpl")),
+            ("404.tpl", include_bytes!("simple/templates/404.tpl")),
+        ],
+        i18n: &[
+            ("en.toml", include_bytes!("simple/i18n/en.toml")),
+            ("zh.toml", include_bytes!("simple/i18n/zh.toml")),
+        ],
+    },
+    BuiltinTheme {
+        name: "dark",
+        manifest: include_str!("dark/theme.toml"),
+        statics: &[
+            ("main.css", include_bytes!("dark/static/main.css")),
+        ],
+        templates: &[],
+        i18n: &[],
+    },
+    BuiltinTheme {
+        name: "minimal",
+        manifest: include_str!("minimal/theme.toml"),
+        statics: &[
+            ("main.css", include_bytes!("minimal/static/main.css")),
+        ],
+        templates: &[
+            ("base.tpl", include_bytes!("minimal/templates/base.tpl")),
+        ],
+        i18n: &[],
+    },
+];
 
-static SIMPLE_FAVICON: &'static [u8] = include_bytes!("simple/static/favicon.png");
-static SIMPLE_LOGO: &'static [u8] = include_bytes!("simple/static/logo.png");
-static SIMPLE_MAIN_CSS: &'static [u8] = include_bytes!("simple/static/main.css");
-static SIMPLE_MAIN_JS: &'static [u8] = include_bytes!("simple/static/main.js");
-static SIMPLE_BASE: &'static [u8] = include_bytes!("simple/templates/base.tpl");
-static SIMPLE_INDEX: &'static [u8] = include_bytes!("simple/templates/index.tpl");
-static SIMPLE_POST: &'static [u8] = include_bytes!("simple/templates/post.tpl");
-static SIMPLE_TAG: &'static [u8] = include_bytes!("simple/templates/tag.tpl");
+/// theme files, keyed by path relative to the theme sub directory
+type Files = BTreeMap<String, Vec<u8>>;
+
+/// exported static files with their contents, path relative to `static` directory
+type Statics = Vec<(String, Vec<u8>)>;
 
 /// theme object
+///
+/// a theme directory is composed of three sub directories:
+///
+/// * `static`: static files, copied into the build directory recursively,
+///   `.scss` files are compiled into `.css` files, `_` prefixed `.scss` partials are skipped.
+/// * `templates`: tera templates, the template name is the path relative to `templates`,
+///   e.g. `partials/nav.tpl`.
+/// * `i18n`: translations of template strings, `<lang>.toml` for every language.
+///
+/// and an optional manifest file `theme.toml`, see `ThemeManifest`.
 pub struct Theme {
     root: PathBuf,
     pub name: String,
-    favicon: Vec<u8>,
-    logo: Vec<u8>,
-    main_css: Vec<u8>,
-    main_js: Vec<u8>,
-    base: Vec<u8>,
-    index: Vec<u8>,
-    post: Vec<u8>,
-    tag: Vec<u8>,
+    /// static files, keyed by path relative to `static` directory
+    statics: Files,
+    /// template files, keyed by path relative to `templates` directory
+    templates: Files,
+    /// site template overrides, keyed by path relative to blog `templates` directory
+    site_templates: Files,
+    /// translation files, keyed by path relative to `i18n` directory
+    i18n: Files,
+    /// theme manifest, options are merged with the parent themes
+    manifest: ThemeManifest,
+    /// theme options resolved from blog `[theme_options]`
+    options: BTreeMap<String, TomlValue>,
+    /// exported static files memoized by `minify`, see `export_statics`
+    exported: Mutex<BTreeMap<bool, Statics>>,
 }
 
 impl Theme {
@@ -32,70 +122,196 @@ impl Theme {
         Theme {
             root: root.as_ref().to_owned(),
             name: String::new(),
-            favicon: Vec::new(),
-            logo: Vec::new(),
-            main_css: Vec::new(),
-            main_js: Vec::new(),
-            base: Vec::new(),
-            index: Vec::new(),
-            post: Vec::new(),
-            tag: Vec::new(),
+            statics: BTreeMap::new(),
+            templates: BTreeMap::new(),
+            site_templates: BTreeMap::new(),
+            i18n: BTreeMap::new(),
+            manifest: ThemeManifest::default(),
+            options: BTreeMap::new(),
+            exported: Mutex::new(BTreeMap::new()),
         }
     }
 
     pub fn clear(&mut self) {
         self.name.clear();
-        self.favicon.clear();
-        self.logo.clear();
-        self.main_css.clear();
-        self.main_js.clear();
-        self.base.clear();
-        self.index.clear();
-        self.post.clear();
-        self.tag.clear();
+        self.statics.clear();
+        self.templates.clear();
+        self.site_templates.clear();
+        self.i18n.clear();
+        self.manifest = ThemeManifest::default();
+        self.options.clear();
+        self.exported.lock().expect("lock exported statics error").clear();
     }
 
+    /// load theme `name`
+    ///
+    /// if `theme.toml` of the theme has `extends = "parent"`, the files missing in the theme are
+    /// resolved from the parent theme recursively. a theme extending its own name, e.g.
+    /// `_themes/simple` extending `simple`, extends the built-in theme of that name.
+    /// the templates in blog `templates` directory are kept apart as site overrides, see
+    /// `site_templates`.
     pub fn load(&mut self, name: &str) -> Result<()> {
         debug!("loading theme: {}", name);
-        let src_dir = self.root.join("_themes").join(name);
-        if src_dir.exists() {
-            let mut favicon_file = File::open(src_dir.join("static/favicon.png"))?;
-            let mut logo_file = File::open(src_dir.join("static/logo.png"))?;
-            let mut main_css_file = File::open(src_dir.join("static/main.css"))?;
-            let mut main_js_file = File::open(src_dir.join("static/main.js"))?;
-            let mut base_file = File::open(src_dir.join("templates/base.tpl"))?;
-            let mut index_file = File::open(src_dir.join("templates/index.tpl"))?;
-            let mut post_file = File::open(src_dir.join("templates/post.tpl"))?;
-            let mut tag_file = File::open(src_dir.join("templates/tag.tpl"))?;
-            self.clear();
-            self.name.push_str(name);
-            favicon_file.read_to_end(&mut self.favicon)?;
-            logo_file.read_to_end(&mut self.logo)?;
-            main_css_file.read_to_end(&mut self.main_css)?;
-            main_js_file.read_to_end(&mut self.main_js)?;
-            base_file.read_to_end(&mut self.base)?;
-            index_file.read_to_end(&mut self.index)?;
-            post_file.read_to_end(&mut self.post)?;
-            tag_file.read_to_end(&mut self.tag)?;
-        } else {
-            if name == "simple" {
-                self.clear();
-                self.name.push_str(name);
-                self.favicon.extend_from_slice(&SIMPLE_FAVICON);
-                self.logo.extend_from_slice(&SIMPLE_LOGO);
-                self.main_css.extend_from_slice(&SIMPLE_MAIN_CSS);
-                self.main_js.extend_from_slice(&SIMPLE_MAIN_JS);
-                self.base.extend_from_slice(&SIMPLE_BASE);
-                self.index.extend_from_slice(&SIMPLE_INDEX);
-                self.post.extend_from_slice(&SIMPLE_POST);
-                self.tag.extend_from_slice(&SIMPLE_TAG);
-            } else {
-                return Err(Error::ThemeNotFound(self.name.clone()));
+        let mut chain: Vec<(String, bool)> = Vec::new();
+        let mut layers = Vec::new();
+        let mut current = Some((name.to_string(), false));
+        while let Some((theme_name, builtin)) = current {
+            if chain.contains(&(theme_name.clone(), builtin)) {
+                return Err(Error::ThemeExtendsCycle(theme_name));
+            }
+            let (manifest, statics, templates, i18n) = self.load_layer(&theme_name, builtin)?;
+            manifest.check_version(&theme_name)?;
+            current = manifest.extends.clone().map(|parent| {
+                let builtin = parent == theme_name;
+                (parent, builtin)
+            });
+            chain.push((theme_name, builtin));
+            layers.push((manifest, statics, templates, i18n));
+        }
+        let site_templates = read_dir_files(&self.root.join("templates"))?;
+        self.clear();
+        self.name.push_str(name);
+        let mut options = BTreeMap::new();
+        for (manifest, statics, templates, i18n) in layers.into_iter().rev() {
+            options.extend(manifest.options.clone());
+            self.manifest = manifest;
+            self.statics.extend(statics);
+            self.templates.extend(templates);
+            self.i18n.extend(i18n);
+        }
+        self.manifest.options = options;
+        self.site_templates = site_templates;
+        Ok(())
+    }
+
+    /// list all themes, the themes in `_themes` directory and the built-in themes
+    pub fn list<P: AsRef<Path>>(root: P) -> Result<Vec<(String, ThemeManifest)>> {
+        let theme = Theme::new(root.as_ref());
+        let mut names: Vec<String> = BUILTIN_THEMES.iter().map(|x| x.name.to_string()).collect();
+        let theme_root = root.as_ref().join("_themes");
+        if theme_root.is_dir() {
+            for entry in ::std::fs::read_dir(theme_root)? {
+                let path = entry?.path();
+                if !path.is_dir() {
+                    continue;
+                }
+                let name = path.file_name()
+                               .and_then(|x| x.to_str())
+                               .expect("theme name error")
+                               .to_string();
+                if !name.starts_with('.') && !names.contains(&name) {
+                    names.push(name);
+                }
             }
         }
+        names.sort();
+        let mut themes = Vec::new();
+        for name in names {
+            let (manifest, _, _, _) = theme.load_layer(&name, false)?;
+            themes.push((name, manifest));
+        }
+        Ok(themes)
+    }
+
+    /// the theme manifest
+    pub fn manifest(&self) -> &ThemeManifest {
+        &self.manifest
+    }
+
+    /// validate and resolve theme options from blog `[theme_options]`
+    pub fn resolve_options(&mut self, site_options: &BTreeMap<String, TomlValue>) -> Result<()> {
+        self.options = self.manifest.resolve_options(&self.name, site_options)?;
         Ok(())
     }
 
+    /// the resolved theme options
+    pub fn options(&self) -> &BTreeMap<String, TomlValue> {
+        &self.options
+    }
+
+    /// load the manifest, static files, templates and translations of theme `name`, without its
+    /// parent
+    ///
+    /// the theme in `_themes` directory is preferred to the built-in theme, unless `builtin`.
+    fn load_layer(&self,
+                  name: &str,
+                  builtin: bool)
+                  -> Result<(ThemeManifest, Files, Files, Files)> {
+        let src_dir = self.root.join("_themes").join(name);
+        if !builtin && src_dir.exists() {
+            let manifest = ThemeManifest::read(&src_dir, name)?;
+            let statics = read_dir_files(&src_dir.join("static"))?;
+            let templates = read_dir_files(&src_dir.join("templates"))?;
+            let i18n = read_dir_files(&src_dir.join("i18n"))?;
+            Ok((manifest, statics, templates, i18n))
+        } else if let Some(builtin) = BUILTIN_THEMES.iter().find(|x| x.name == name) {
+            let to_files = |files: &[(&str, &[u8])]| -> Files {
+                files.iter().map(|&(path, content)| (path.to_string(), content.to_vec())).collect()
+            };
+            let statics = to_files(builtin.statics);
+            let templates = to_files(builtin.templates);
+            let i18n = to_files(builtin.i18n);
+            Ok((toml::from_str(builtin.manifest)?, statics, templates, i18n))
+        } else {
+            Err(Error::ThemeNotFound(name.to_string()))
+        }
+    }
+
+    /// template names with their contents
+    pub fn templates(&self) -> Result<Vec<(&str, &str)>> {
+        self.template_list(&self.templates)
+    }
+
+    /// site template names with their contents, which override the templates of theme
+    pub fn site_templates(&self) -> Result<Vec<(&str, &str)>> {
+        self.template_list(&self.site_templates)
+    }
+
+    fn template_list<'a>(&self, files: &'a Files) -> Result<Vec<(&'a str, &'a str)>> {
+        let mut templates = Vec::new();
+        for (name, content) in files {
+            let content = str::from_utf8(content).map_err(|_| {
+                Error::Template(format!("template {} of theme {} is not utf-8", name, self.name))
+            })?;
+            templates.push((name.as_str(), content));
+        }
+        Ok(templates)
+    }
+
+    /// translations of `language`, with the translations of default language as fallback
+    pub fn i18n(&self, language: &str) -> Result<I18n> {
+        let mut tables = Vec::new();
+        let mut languages = vec![DEFAULT_LANGUAGE];
+        if language != DEFAULT_LANGUAGE {
+            languages.push(language);
+        }
+        for lang in languages {
+            let path = format!("{}.toml", lang);
+            let content = match self.i18n.get(&path) {
+                Some(content) => content,
+                None => {
+                    debug!("theme({}) has no translations of language {}", self.name, lang);
+                    continue;
+                },
+            };
+            let table = str::from_utf8(content)
+                            .map_err(|e| e.to_string())
+                            .and_then(|x| toml::from_str(x).map_err(|e| e.to_string()))
+                            .map_err(|e| {
+                                Error::ThemeInvalid(self.name.clone(),
+                                                    format!("i18n/{}: {}", path, e))
+                            })?;
+            check_date_formats(&self.name, &path, &table)?;
+            tables.push(table);
+        }
+        Ok(I18n::new(language, tables))
+    }
+
+    /// hash of all theme files
+    pub fn hash(&self) -> u64 {
+        hash_of(&(&self.name, &self.statics, &self.templates, &self.site_templates, &self.i18n))
+    }
+
     pub fn init_dir(&self, name: &str) -> Result<()> {
         let dest_dir = self.root.join("_themes").join(name);
         if dest_dir.exists() {
@@ -104,49 +320,414 @@ impl Theme {
         }
         debug!("init theme({}) ...", name);
 
-        let mut favicon = create_file(&dest_dir.join("static/favicon.png"))?;
-        favicon.write_all(&self.favicon)?;
-
-        let mut logo = create_file(&dest_dir.join("static/logo.png"))?;
-        logo.write_all(&self.logo)?;
+        let mut manifest = self.manifest.clone();
+        manifest.name = Some(name.to_string());
+        manifest.extends = None;
+        if manifest.origin.is_none() && builtin_files(&self.name).is_some() {
+            manifest.origin = Some(self.name.clone());
+        }
+        if let Some(ref origin) = manifest.origin {
+            upgrade::write_origin(&dest_dir, origin)?;
+        }
+        let mut f = create_file(&dest_dir.join("theme.toml"))?;
+        f.write_all(toml::to_string(&manifest)?.as_bytes())?;
 
-        let mut main_css = create_file(&dest_dir.join("static/main.css"))?;
-        main_css.write_all(&self.main_css)?;
+        for (path, content) in &self.statics {
+            let mut f = create_file(&dest_dir.join("static").join(path))?;
+            f.write_all(content)?;
+        }
+        for (path, content) in &self.templates {
+            let mut f = create_file(&dest_dir.join("templates").join(path))?;
+            f.write_all(content)?;
+        }
+        for (path, content) in &self.i18n {
+            let mut f = create_file(&dest_dir.join("i18n").join(path))?;
+            f.write_all(content)?;
+        }
+        Ok(())
+    }
 
-        let mut main_js = create_file(&dest_dir.join("static/main.js"))?;
-        main_js.write_all(&self.main_js)?;
+    /// compile the scss static file `path` into css
+    ///
+    /// imports are resolved from the theme static files.
+    fn compile_scss(&self, path: &str) -> Result<Vec<u8>> {
+        let fs = StaticFs(&self.statics);
+        let options = grass::Options::default().fs(&fs);
+        match grass::from_path(path, &options) {
+            Ok(css) => Ok(css.into_bytes()),
+            Err(why) => Err(scss_error(&self.name, *why)),
+        }
+    }
 
-        let mut base = create_file(&dest_dir.join("templates/base.tpl"))?;
-        base.write_all(&self.base)?;
+    /// static files with their exported contents, minify css and javascript if `minify` is set
+    ///
+    /// the result is memoized until the theme is loaded again, so scss is compiled only once for
+    /// all language renderers and the export.
+    fn export_statics(&self, minify: bool) -> Result<Statics> {
+        let mut exported = self.exported.lock().expect("lock exported statics error");
+        if let Some(statics) = exported.get(&minify) {
+            return Ok(statics.clone());
+        }
+        let mut statics = Vec::new();
+        for (path, content) in &self.statics {
+            let (path, content) = match extension(path) {
+                "scss" if is_partial(path) => continue,
+                "scss" => (replace_extension(path, "css"), self.compile_scss(path)?),
+                _ => (path.clone(), content.clone()),
+            };
+            let content = match extension(&path) {
+                "css" if minify => minify_css(&content),
+                "js" if minify => minify_js(&content),
+                _ => content,
+            };
+            statics.push((path, content));
+        }
+        exported.insert(minify, statics.clone());
+        Ok(statics)
+    }
 
-        let mut index = create_file(&dest_dir.join("templates/index.tpl"))?;
-        index.write_all(&self.index)?;
+    /// static files exported by `export_static` and their contents
+    ///
+    /// keyed by path relative to the build directory.
+    pub fn static_outputs(&self, minify: bool) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        let mut outputs = Vec::new();
+        for (path, content) in self.export_statics(minify)? {
+            let dest = Path::new("static").join(fingerprint(&path, &content));
+            outputs.push((Path::new("static").join(&path), content.clone()));
+            outputs.push((dest, content));
+        }
+        Ok(outputs)
+    }
 
-        let mut post = create_file(&dest_dir.join("templates/post.tpl"))?;
-        post.write_all(&self.post)?;
+    /// static files exported by `export_static`, relative to the build directory
+    pub fn static_files(&self, minify: bool) -> Result<Vec<PathBuf>> {
+        Ok(self.static_outputs(minify)?.into_iter().map(|(path, _)| path).collect())
+    }
 
-        let mut tag = create_file(&dest_dir.join("templates/tag.tpl"))?;
-        tag.write_all(&self.tag)?;
+    /// url of the content-hashed static files, keyed by path relative to `static` directory
+    pub fn assets(&self, minify: bool) -> Result<BTreeMap<String, String>> {
+        let mut assets = BTreeMap::new();
+        for (path, content) in self.export_statics(minify)? {
+            let url = format!("/static/{}", fingerprint(&path, &content));
+            assets.insert(path, url);
+        }
+        Ok(assets)
+    }
 
+    /// export static files into `root/static`, minify css and javascript if `minify` is set
+    ///
+    /// `.scss` files of theme are compiled into `.css` files.
+    /// every static file is exported twice, with its origin name and its content-hashed name,
+    /// e.g. `main.css` and `main.3f2a1c0b.css`.
+    pub fn export_static<P: AsRef<Path>>(&self, root: P, minify: bool) -> Result<()> {
+        debug!("exporting theme({}) static ...", self.name);
+        for (path, content) in self.static_outputs(minify)? {
+            let mut f = create_file(&root.as_ref().join(path))?;
+            f.write_all(&content)?;
+        }
         Ok(())
     }
+}
 
-    pub fn export_static<P: AsRef<Path>>(&self, root: P) -> Result<()> {
-        debug!("exporting theme({}) static ...", self.name);
-        let dest_dir = root.as_ref();
+/// files of built-in theme `name` merged with its built-in parents
+///
+/// keyed by path relative to theme directory, e.g. `templates/base.tpl`.
+fn builtin_files(name: &str) -> Option<Files> {
+    let builtin = BUILTIN_THEMES.iter().find(|x| x.name == name)?;
+    let manifest: ThemeManifest = toml::from_str(builtin.manifest)
+                                      .expect("built-in theme manifest error");
+    let mut files = match manifest.extends {
+        Some(ref parent) => builtin_files(parent)?,
+        None => Files::new(),
+    };
+    for &(path, content) in builtin.statics {
+        files.insert(format!("static/{}", path), content.to_vec());
+    }
+    for &(path, content) in builtin.templates {
+        files.insert(format!("templates/{}", path), content.to_vec());
+    }
+    for &(path, content) in builtin.i18n {
+        files.insert(format!("i18n/{}", path), content.to_vec());
+    }
+    Some(files)
+}
+
+/// check the `date_format` and `datetime_format` of translations `table` of theme `name`
+fn check_date_formats(name: &str, path: &str, table: &Table) -> Result<()> {
+    for key in &["date_format", "datetime_format"] {
+        if let Some(fmt) = table.get(*key).and_then(|x| x.as_str()) {
+            if !is_valid_date_format(fmt) {
+                return Err(Error::ThemeInvalid(name.to_string(),
+                                               format!("i18n/{}: invalid {} {}", path, key, fmt)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// read all files under `dir` recursively, keyed by path relative to `dir`
+///
+/// hidden files are skipped, return empty map if `dir` does not exist.
+fn read_dir_files(dir: &Path) -> Result<Files> {
+    let mut files = Files::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    let walker = WalkDir::new(dir).min_depth(1).into_iter();
+    for entry in walker.filter_entry(|e| !e.file_name().to_string_lossy().starts_with('.')) {
+        let entry = entry.expect("get walker entry error");
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel_path = entry.path().strip_prefix(dir)?;
+        let name = rel_path.components()
+                           .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                           .collect::<Vec<String>>()
+                           .join("/");
+        let mut content = Vec::new();
+        File::open(entry.path())?.read_to_end(&mut content)?;
+        files.insert(name, content);
+    }
+    Ok(files)
+}
 
-        let mut favicon = create_file(&dest_dir.join("static/favicon.png"))?;
-        favicon.write_all(&self.favicon)?;
+/// file extension of static file `path`
+fn extension(path: &str) -> &str {
+    Path::new(path).extension().and_then(|x| x.to_str()).unwrap_or("")
+}
 
-        let mut logo = create_file(&dest_dir.join("static/logo.png"))?;
-        logo.write_all(&self.logo)?;
+/// whether static file `path` is a scss partial, e.g. `_vars.scss`
+fn is_partial(path: &str) -> bool {
+    path.rsplit('/').next().map(|x| x.starts_with('_')).unwrap_or(false)
+}
 
-        let mut main_css = create_file(&dest_dir.join("static/main.css"))?;
-        main_css.write_all(&self.main_css)?;
+/// replace the extension of static file `path` with `ext`
+fn replace_extension(path: &str, ext: &str) -> String {
+    match path.rfind('.') {
+        Some(pos) => format!("{}.{}", &path[..pos], ext),
+        None => format!("{}.{}", path, ext),
+    }
+}
 
-        let mut main_js = create_file(&dest_dir.join("static/main.js"))?;
-        main_js.write_all(&self.main_js)?;
+/// in-memory file system of theme static files, used for resolving scss imports
+#[derive(Debug)]
+struct StaticFs<'a>(&'a Files);
 
-        Ok(())
+impl<'a> StaticFs<'a> {
+    fn name(path: &Path) -> String {
+        let mut parts = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(x) => parts.push(x.to_string_lossy().into_owned()),
+                Component::ParentDir => {
+                    parts.pop();
+                },
+                _ => {},
+            }
+        }
+        parts.join("/")
+    }
+}
+
+impl<'a> Fs for StaticFs<'a> {
+    fn is_dir(&self, path: &Path) -> bool {
+        let prefix = format!("{}/", StaticFs::name(path));
+        prefix == "/" || self.0.keys().any(|x| x.starts_with(&prefix))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.0.contains_key(&StaticFs::name(path))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.0.get(&StaticFs::name(path)) {
+            Some(content) => Ok(content.clone()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("{}", path.display()))),
+        }
+    }
+}
+
+/// convert scss compile error of theme `name` into `Error::Scss` with file and line
+fn scss_error(name: &str, err: grass::Error) -> Error {
+    match err.kind() {
+        grass::ErrorKind::ParseError { message, loc, .. } => {
+            let file = Path::new("_themes").join(name).join("static").join(loc.file.name());
+            Error::Scss(file, loc.begin.line + 1, message)
+        },
+        grass::ErrorKind::IoError(why) => Error::Argument(format!("scss io error: {}", why)),
+        grass::ErrorKind::FromUtf8Error(why) => {
+            Error::Argument(format!("scss utf-8 error: {}", why))
+        },
+        _ => Error::Argument("scss compile error".to_string()),
+    }
+}
+
+/// content-hashed file name, e.g. `main.css` -> `main.3f2a1c0b.css`
+fn fingerprint(name: &str, content: &[u8]) -> String {
+    let hash = format!("{:016x}", hash_of(content));
+    let file_start = name.rfind('/').map(|x| x + 1).unwrap_or(0);
+    match name[file_start..].rfind('.') {
+        Some(pos) => {
+            let pos = file_start + pos;
+            format!("{}.{}{}", &name[..pos], &hash[..8], &name[pos..])
+        },
+        None => format!("{}.{}", name, &hash[..8]),
+    }
+}
+
+/// template global function `asset_url(path="main.css")`, return the content-hashed url
+pub fn make_asset_url_fn(assets: BTreeMap<String, String>) -> GlobalFn {
+    Box::new(move |args| -> TeraResult<TeraValue> {
+        let path = match args.get("path").and_then(|x| x.as_str()) {
+            Some(path) => path.trim_start_matches("/static/").trim_start_matches('/'),
+            None => return Err("global function `asset_url` requires a `path` argument".into()),
+        };
+        match assets.get(path) {
+            Some(url) => Ok(TeraValue::String(url.clone())),
+            None => {
+                Err(format!("global function `asset_url`: static file {} not found", path).into())
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+
+    /// a temporary blog root directory with theme `name` of `files`
+    fn test_root(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("mdblog-theme-{}-{}", process::id(), name));
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        for &(path, content) in files {
+            let path = root.join("_themes").join(name).join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn fingerprint_keeps_directory_and_extension() {
+        let hash = format!("{:016x}", hash_of(&b"body"[..]));
+        assert_eq!(fingerprint("main.css", b"body"), format!("main.{}.css", &hash[..8]));
+        assert_eq!(fingerprint("v1.0/app", b"body"), format!("v1.0/app.{}", &hash[..8]));
+        assert_ne!(fingerprint("main.css", b"body"), fingerprint("main.css", b"other"));
+    }
+
+    #[test]
+    fn asset_url_returns_fingerprinted_url() {
+        let root = test_root("assets", &[("static/main.css", "body { color: red; }"),
+                                         ("static/img/logo.png", "png")]);
+        let mut theme = Theme::new(&root);
+        theme.load("assets").unwrap();
+        let assets = theme.assets(false).unwrap();
+        let css_url = format!("/static/{}", fingerprint("main.css", b"body { color: red; }"));
+        assert_eq!(assets["main.css"], css_url);
+        let logo_url = format!("/static/{}", fingerprint("img/logo.png", b"png"));
+        assert_eq!(assets["img/logo.png"], logo_url);
+
+        let asset_url = make_asset_url_fn(assets);
+        for path in &["main.css", "/static/main.css", "/main.css"] {
+            let mut args = HashMap::new();
+            args.insert("path".to_string(), TeraValue::String(path.to_string()));
+            assert_eq!(asset_url(args).unwrap(), TeraValue::String(css_url.clone()));
+        }
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), TeraValue::String("missing.css".to_string()));
+        assert!(asset_url(args).is_err());
+        assert!(asset_url(HashMap::new()).is_err());
+
+        assert_eq!(theme.exported.lock().unwrap().len(), 1);
+        theme.load("assets").unwrap();
+        assert!(theme.exported.lock().unwrap().is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn compiles_scss_and_skips_partials() {
+        let root = test_root("scss", &[("static/main.scss", "@import \"vars\";\na { color: $c; }"),
+                                       ("static/_vars.scss", "$c: red;")]);
+        let mut theme = Theme::new(&root);
+        theme.load("scss").unwrap();
+        let outputs = theme.static_outputs(false).unwrap();
+        let paths: Vec<&Path> = outputs.iter().map(|x| x.0.as_path()).collect();
+        let css = fingerprint("main.css", &outputs[0].1);
+        assert_eq!(paths, vec![Path::new("static/main.css"), &Path::new("static").join(css)]);
+        assert!(String::from_utf8_lossy(&outputs[0].1).contains("color: red"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reports_scss_error_file_and_line() {
+        let root = test_root("scss-error", &[("static/main.scss", "@import \"vars\";"),
+                                             ("static/_vars.scss", "$c: red;\na { color: $d; }")]);
+        let mut theme = Theme::new(&root);
+        theme.load("scss-error").unwrap();
+        match theme.static_files(false) {
+            Err(Error::Scss(file, line, _)) => {
+                assert_eq!(file, Path::new("_themes/scss-error/static/_vars.scss"));
+                assert_eq!(line, 2);
+            },
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn load_resolves_extends_chain() {
+        let root = test_root("child", &[("theme.toml", "extends = \"parent\""),
+                                        ("templates/index.tpl", "child index")]);
+        for &(path, content) in &[("theme.toml", "extends = \"simple\""),
+                                  ("templates/index.tpl", "parent index"),
+                                  ("templates/post.tpl", "parent post")] {
+            let path = root.join("_themes/parent").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut theme = Theme::new(&root);
+        theme.load("child").unwrap();
+        let templates: BTreeMap<&str, &str> = theme.templates().unwrap().into_iter().collect();
+        assert_eq!(templates["index.tpl"], "child index");
+        assert_eq!(templates["post.tpl"], "parent post");
+        assert!(templates.contains_key("tag.tpl"));
+        assert!(theme.statics.contains_key("main.css"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn load_extends_builtin_theme_of_own_name() {
+        let root = test_root("simple", &[("theme.toml", "extends = \"simple\""),
+                                         ("templates/index.tpl", "site index")]);
+        let mut theme = Theme::new(&root);
+        theme.load("simple").unwrap();
+        let templates: BTreeMap<&str, &str> = theme.templates().unwrap().into_iter().collect();
+        assert_eq!(templates["index.tpl"], "site index");
+        assert!(templates.contains_key("post.tpl"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn load_detects_extends_cycle() {
+        let root = test_root("a", &[("theme.toml", "extends = \"b\"")]);
+        fs::create_dir_all(root.join("_themes/b")).unwrap();
+        fs::write(root.join("_themes/b/theme.toml"), "extends = \"a\"").unwrap();
+        let mut theme = Theme::new(&root);
+        match theme.load("a") {
+            Err(Error::ThemeExtendsCycle(name)) => assert_eq!(name, "a"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        match theme.load("missing") {
+            Err(Error::ThemeNotFound(name)) => assert_eq!(name, "missing"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        fs::remove_dir_all(&root).unwrap();
     }
 }
@@ -0,0 +1,9 @@
+{% extends "base.tpl" %}
+
+
+{% block main %}
+  <h1>{{ title }}</h1>
+  <article>
+    <p><a href="{{ home_url }}">{{ t(key="back_home") }}</a></p>
+  </article>
+{% endblock main %}
@@ -8,10 +8,13 @@
 
       <ul id="article_footer">
         {% if post_tags %}
-           <li>tags: {% for tag in post_tags %}<a href="{{ tag.url }}">{{ tag.name }}<sup>{{ tag.num }}</sup></a>{% endfor %}</li>
+           <li>{{ t(key="post_tags") }}: {% for tag in post_tags %}<a href="{{ tag.url }}">{{ tag.name }}<sup>{{ tag.num }}</sup></a>{% endfor %}</li>
         {% endif %}
         {% if datetime %}
-           <li>date: {{ datetime }}</li>
+           <li>{{ t(key="post_date") }}: {{ datetime }}</li>
+        {% endif %}
+        {% if translations | length > 1 %}
+           <li>{{ t(key="post_translations") }}: {% for translation in translations %}{% if translation.lang != language %}<a href="{{ translation.url }}" hreflang="{{ translation.lang }}">{{ translation.lang }}</a>{% endif %}{% endfor %}</li>
         {% endif %}
       </ul>
     </article>
@@ -0,0 +1,261 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::str;
+
+use diffy::{self, DiffOptions};
+
+use errors::{Error, Result};
+use utils::create_file;
+use super::{builtin_files, read_dir_files, Files, ThemeManifest};
+
+/// directory in theme directory keeping the built-in theme files the theme is created from
+static ORIGIN_DIR: &'static str = ".origin";
+/// directory in theme directory keeping the built-in versions of conflicted files by upgrade
+static UPGRADE_DIR: &'static str = ".upgrade";
+
+/// write the files of built-in theme `origin` into the origin directory of theme directory `dir`
+pub fn write_origin(dir: &Path, origin: &str) -> Result<()> {
+    let origin_dir = dir.join(ORIGIN_DIR);
+    if origin_dir.exists() {
+        fs::remove_dir_all(&origin_dir)?;
+    }
+    let files = builtin_files(origin).ok_or_else(|| Error::ThemeNotFound(origin.to_string()))?;
+    for (path, content) in &files {
+        let mut f = create_file(&origin_dir.join(path))?;
+        f.write_all(content)?;
+    }
+    Ok(())
+}
+
+/// unified diff between the built-in origin and the on-disk theme `name`
+pub fn diff_theme(root: &Path, name: &str) -> Result<String> {
+    let (dir, origin) = theme_origin(root, name)?;
+    let theirs = builtin_files(&origin).expect("get built-in theme files error");
+    let ours = theme_files(&dir)?;
+    let mut output = String::new();
+    for path in all_paths(&[&theirs, &ours]) {
+        let old_name = format!("a/{}", path);
+        let new_name = format!("b/{}", path);
+        match (theirs.get(&path), ours.get(&path)) {
+            (Some(old), Some(new)) if old == new => {},
+            (Some(old), Some(new)) => {
+                match (str::from_utf8(old), str::from_utf8(new)) {
+                    (Ok(old), Ok(new)) => {
+                        let patch = DiffOptions::new().set_original_filename(old_name)
+                                                      .set_modified_filename(new_name)
+                                                      .create_patch(old, new);
+                        output.push_str(&patch.to_string());
+                    },
+                    _ => {
+                        output.push_str(&format!("Binary files {} and {} differ\n",
+                                                 old_name,
+                                                 new_name))
+                    },
+                }
+            },
+            (Some(_), None) => output.push_str(&format!("Only in {}: {}\n", origin, path)),
+            (None, Some(_)) => output.push_str(&format!("Only in {}: {}\n", dir.display(), path)),
+            (None, None) => unreachable!(),
+        }
+    }
+    Ok(output)
+}
+
+/// upgrade the on-disk theme `name` to the current version of its built-in origin
+///
+/// the changes between the origin files the theme is created from and the current built-in
+/// files are merged into the theme files. on conflict, the theme file is kept and the
+/// built-in file is written into the `.upgrade` directory of theme, outside the loaded files.
+/// without the origin files, e.g. a theme created by an old mdblog, every theme file differing
+/// from the built-in file is a conflict.
+pub fn upgrade_theme(root: &Path, name: &str) -> Result<()> {
+    let (dir, origin) = theme_origin(root, name)?;
+    let theirs = builtin_files(&origin).expect("get built-in theme files error");
+    let ours = theme_files(&dir)?;
+    if !dir.join(ORIGIN_DIR).is_dir() {
+        warn!("theme({}) has no origin files in {}, can not merge changes, every file differing \
+               from built-in theme({}) is written to {}",
+              name,
+              ORIGIN_DIR,
+              origin,
+              UPGRADE_DIR);
+    }
+    let base = theme_files(&dir.join(ORIGIN_DIR))?;
+    let upgrade_dir = dir.join(UPGRADE_DIR);
+    if upgrade_dir.exists() {
+        fs::remove_dir_all(&upgrade_dir)?;
+    }
+    for path in all_paths(&[&base, &theirs, &ours]) {
+        let dest = dir.join(&path);
+        match (base.get(&path), theirs.get(&path), ours.get(&path)) {
+            (_, Some(new), Some(current)) if new == current => {},
+            (Some(old), Some(new), _) if old == new => {},
+            (Some(old), None, Some(current)) if old == current => {
+                info!("remove {}", path);
+                fs::remove_file(&dest)?;
+            },
+            (Some(_), None, _) => {},
+            (None, Some(new), None) => {
+                info!("add {}", path);
+                create_file(&dest)?.write_all(new)?;
+            },
+            (Some(old), Some(new), Some(current)) if old == current => {
+                info!("update {}", path);
+                create_file(&dest)?.write_all(new)?;
+            },
+            (Some(old), Some(new), Some(current)) => {
+                match merge(old, current, new) {
+                    Some(merged) => {
+                        info!("merge {}", path);
+                        create_file(&dest)?.write_all(&merged)?;
+                    },
+                    None => write_conflict(&dir, &path, new)?,
+                }
+            },
+            (_, Some(new), _) => write_conflict(&dir, &path, new)?,
+            (None, None, _) => {},
+        }
+    }
+    write_origin(&dir, &origin)?;
+    Ok(())
+}
+
+/// the directory and built-in origin of theme `name`
+///
+/// the origin is `origin` of the theme manifest, or the theme name if it is a built-in theme.
+fn theme_origin(root: &Path, name: &str) -> Result<(::std::path::PathBuf, String)> {
+    let dir = root.join("_themes").join(name);
+    if !dir.is_dir() {
+        return Err(Error::ThemeNotFound(name.to_string()));
+    }
+    let manifest = ThemeManifest::read(&dir, name)?;
+    let origin = manifest.origin.unwrap_or_else(|| name.to_string());
+    if builtin_files(&origin).is_none() {
+        let reason = "not created from a built-in theme, set `origin` of theme.toml to the \
+                      built-in theme it is created from";
+        return Err(Error::ThemeInvalid(name.to_string(), reason.to_string()));
+    }
+    Ok((dir, origin))
+}
+
+/// static files, templates and translations of theme directory `dir`, keyed by path relative
+/// to `dir`
+fn theme_files(dir: &Path) -> Result<Files> {
+    let mut files = Files::new();
+    for sub_dir in &["static", "templates", "i18n"] {
+        for (path, content) in read_dir_files(&dir.join(sub_dir))? {
+            files.insert(format!("{}/{}", sub_dir, path), content);
+        }
+    }
+    Ok(files)
+}
+
+/// sorted paths of all `files`
+fn all_paths(files: &[&Files]) -> BTreeSet<String> {
+    files.iter().flat_map(|x| x.keys().cloned()).collect()
+}
+
+/// three-way merge of text files, return `None` on conflict
+fn merge(base: &[u8], ours: &[u8], theirs: &[u8]) -> Option<Vec<u8>> {
+    match (str::from_utf8(base), str::from_utf8(ours), str::from_utf8(theirs)) {
+        (Ok(base), Ok(ours), Ok(theirs)) => {
+            diffy::merge(base, ours, theirs).ok().map(|x| x.into_bytes())
+        },
+        _ => None,
+    }
+}
+
+/// write the built-in file `path` of the conflicted theme file into the upgrade directory
+fn write_conflict(dir: &Path, path: &str, content: &[u8]) -> Result<()> {
+    warn!("conflict {}, the built-in version is written to {}/{}", path, UPGRADE_DIR, path);
+    create_file(&dir.join(UPGRADE_DIR).join(path))?.write_all(content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    /// a temporary blog root directory
+    fn test_root(name: &str) -> ::std::path::PathBuf {
+        let root = env::temp_dir().join(format!("mdblog-upgrade-{}-{}", process::id(), name));
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    /// built-in file `path` of the `simple` theme, with its first line replaced by `first`
+    fn replace_first_line(path: &str, first: &str) -> String {
+        let files = builtin_files("simple").unwrap();
+        let content = str::from_utf8(&files[path]).unwrap();
+        let rest = content.split_once('\n').map(|x| x.1).unwrap_or("");
+        format!("{}\n{}", first, rest)
+    }
+
+    fn write(path: &Path, content: &str) {
+        create_file(path).unwrap().write_all(content.as_bytes()).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        String::from_utf8(fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn upgrade_merges_local_changes_and_keeps_conflicts() {
+        let root = test_root("merge");
+        let dir = root.join("_themes/mine");
+        write(&dir.join("theme.toml"), "origin = \"simple\"\n");
+        write_origin(&dir, "simple").unwrap();
+        let builtin = builtin_files("simple").unwrap();
+
+        let old_index = replace_first_line("templates/index.tpl", "{# old #}");
+        write(&dir.join(ORIGIN_DIR).join("templates/index.tpl"), &old_index);
+        write(&dir.join("templates/index.tpl"), &format!("{}{{# local #}}\n", old_index));
+        let old_post = replace_first_line("templates/post.tpl", "{# old #}");
+        let mine_post = replace_first_line("templates/post.tpl", "{# mine #}");
+        write(&dir.join(ORIGIN_DIR).join("templates/post.tpl"), &old_post);
+        write(&dir.join("templates/post.tpl"), &mine_post);
+
+        upgrade_theme(&root, "mine").unwrap();
+        let index = str::from_utf8(&builtin["templates/index.tpl"]).unwrap();
+        assert_eq!(read(&dir.join("templates/index.tpl")), format!("{}{{# local #}}\n", index));
+        assert!(!dir.join(UPGRADE_DIR).join("templates/index.tpl").exists());
+        assert_eq!(read(&dir.join("templates/post.tpl")), mine_post);
+        assert_eq!(fs::read(dir.join(UPGRADE_DIR).join("templates/post.tpl")).unwrap(),
+                   builtin["templates/post.tpl"]);
+        assert_eq!(fs::read(dir.join(ORIGIN_DIR).join("templates/post.tpl")).unwrap(),
+                   builtin["templates/post.tpl"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn upgrade_without_origin_files_writes_differing_files() {
+        let root = test_root("no-origin");
+        let dir = root.join("_themes/simple");
+        let builtin = builtin_files("simple").unwrap();
+        write(&dir.join("templates/index.tpl"), "{# mine #}");
+        let mut f = create_file(&dir.join("static/main.css")).unwrap();
+        f.write_all(&builtin["static/main.css"]).unwrap();
+
+        upgrade_theme(&root, "simple").unwrap();
+        assert_eq!(read(&dir.join("templates/index.tpl")), "{# mine #}");
+        assert_eq!(fs::read(dir.join(UPGRADE_DIR).join("templates/index.tpl")).unwrap(),
+                   builtin["templates/index.tpl"]);
+        assert!(!dir.join(UPGRADE_DIR).join("static/main.css").exists());
+        assert!(dir.join(ORIGIN_DIR).join("templates/index.tpl").exists());
+
+        write(&root.join("_themes/copied/templates/index.tpl"), "{# copied #}");
+        match upgrade_theme(&root, "copied") {
+            Err(Error::ThemeInvalid(name, _)) => assert_eq!(name, "copied"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,18 +1,121 @@
 use errors::{Result, Error};
-use std::fs::File;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
 use failure::Fail;
+use walkdir::WalkDir;
 
 /// create the file of `path`
 ///
 /// if parent of `path` does not existed, create it first.
+/// if `path` already existed, remove it first, so other hard links of it are not modified.
 pub fn create_file(path: &Path) -> Result<File> {
     if let Some(p) = path.parent() {
-        ::std::fs::create_dir_all(p)?;
+        fs::create_dir_all(p)?;
+    }
+    if path.exists() {
+        fs::remove_file(path)?;
     }
     Ok(File::create(path)?)
 }
 
+/// copy the file `src` to `dest`
+///
+/// `dest` is created by `create_file`.
+pub fn copy_file(src: &Path, dest: &Path) -> Result<()> {
+    let mut src_file = File::open(src)?;
+    let mut dest_file = create_file(dest)?;
+    io::copy(&mut src_file, &mut dest_file)?;
+    Ok(())
+}
+
+/// the hidden sibling directory of `dir` with `suffix`, e.g. `.dir.suffix`
+pub fn sibling_dir(dir: &Path, suffix: &str) -> Result<PathBuf> {
+    let name = dir.file_name()
+                  .and_then(|x| x.to_str())
+                  .ok_or_else(|| Error::Argument(format!("invalid directory: {}", dir.display())))?;
+    Ok(dir.with_file_name(format!(".{}.{}", name, suffix)))
+}
+
+/// mirror directory `src` to `dest` with hard links
+///
+/// falls back to copying if files can not be hard linked.
+pub fn link_dir(src: &Path, dest: &Path) -> Result<()> {
+    for entry in WalkDir::new(src) {
+        let entry = entry.expect("get walker entry error");
+        let target = dest.join(entry.path().strip_prefix(src)?);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if fs::hard_link(entry.path(), &target).is_err() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// swap directory `src` into place of directory `dest`
+///
+/// an existing `dest` is atomically exchanged with `src`, so `dest` always exists and is never
+/// partially written, and `src` holds the old `dest` afterwards.
+/// if the exchange is not supported by the system, `dest` is renamed to a backup directory
+/// before `src` is renamed into place, so `dest` is missing only between the two renames.
+/// fails if `dest` is a mount point.
+pub fn swap_dir(src: &Path, dest: &Path) -> Result<()> {
+    if !dest.exists() {
+        fs::rename(src, dest)?;
+        return Ok(());
+    }
+    if let Err(why) = exchange_paths(src, dest) {
+        debug!("can not exchange {} and {}: {}", src.display(), dest.display(), why);
+        rename_paths(src, dest)?;
+    }
+    Ok(())
+}
+
+/// exchange directories `src` and `dest` by renaming `dest` to a backup directory first
+fn rename_paths(src: &Path, dest: &Path) -> Result<()> {
+    let backup = sibling_dir(dest, "backup")?;
+    if backup.exists() {
+        fs::remove_dir_all(&backup)?;
+    }
+    fs::rename(dest, &backup)?;
+    if let Err(why) = fs::rename(src, dest) {
+        fs::rename(&backup, dest)?;
+        return Err(why.into());
+    }
+    fs::rename(&backup, src)?;
+    Ok(())
+}
+
+/// exchange paths `a` and `b` by `renameat2(RENAME_EXCHANGE)`
+#[cfg(target_os = "linux")]
+fn exchange_paths(a: &Path, b: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    const RENAME_EXCHANGE: libc::c_uint = 1 << 1;
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    let res = unsafe {
+        libc::syscall(libc::SYS_renameat2,
+                      libc::AT_FDCWD,
+                      a.as_ptr(),
+                      libc::AT_FDCWD,
+                      b.as_ptr(),
+                      RENAME_EXCHANGE)
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// exchange paths `a` and `b`, not supported
+#[cfg(not(target_os = "linux"))]
+fn exchange_paths(_: &Path, _: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "exchanging directories is not supported"))
+}
+
 /// log error chain
 pub fn log_error(err: &Error) {
     for cause in err.causes() {
@@ -22,4 +125,32 @@ pub fn log_error(err: &Error) {
     if let Some(backtrace) = err.backtrace() {
         error!("backtrace: {:?}", backtrace);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn rename_paths_exchanges_directories() {
+        let root = env::temp_dir().join(format!("mdblog-utils-{}", process::id()));
+        let (src, dest) = (root.join(".build.staging"), root.join("build"));
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(src.join("new.html"), "").unwrap();
+        fs::write(dest.join("old.html"), "").unwrap();
+
+        rename_paths(&src, &dest).unwrap();
+        assert!(dest.join("new.html").exists() && !dest.join("old.html").exists());
+        assert!(src.join("old.html").exists() && !src.join("new.html").exists());
+        assert!(!root.join(".build.backup").exists());
+
+        fs::remove_dir_all(&src).unwrap();
+        assert!(rename_paths(&src, &dest).is_err());
+        assert!(dest.join("new.html").exists());
+        assert!(!root.join(".build.backup").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}